@@ -0,0 +1,82 @@
+// Legitimate domains that are never scored. Entries are compared with the domain
+// as it appears on the certificate (ASCII/punycode form), case-insensitively.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Allowlist {
+    // Exact domains, e.g. "www.facebook.com"
+    pub domains: Vec<String>,
+    // Registrable domains, covering all of their subdomains, e.g. "facebook.com"
+    pub registrable: Vec<String>,
+    // Wildcard patterns where '*' matches any characters, e.g. "*.fbcdn.net"
+    pub patterns: Vec<String>,
+}
+
+impl Allowlist {
+    pub fn contains(&self, domain: &str, registrable: Option<&str>) -> bool {
+        let domain = domain.trim_end_matches('.');
+
+        if self.domains.iter().any(|d| d.eq_ignore_ascii_case(domain)) {
+            return true;
+        }
+
+        if let Some(registrable) = registrable {
+            if self
+                .registrable
+                .iter()
+                .any(|r| r.eq_ignore_ascii_case(registrable))
+            {
+                return true;
+            }
+        }
+
+        let domain = domain.to_ascii_lowercase();
+        self.patterns.iter().any(|pattern| {
+            wildcard_match(pattern.to_ascii_lowercase().as_bytes(), domain.as_bytes())
+        })
+    }
+}
+
+pub(crate) fn wildcard_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|skip| wildcard_match(rest, &text[skip..])),
+        Some((c, rest)) => match text.split_first() {
+            Some((t, text_rest)) if t == c => wildcard_match(rest, text_rest),
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowlist() -> Allowlist {
+        Allowlist {
+            domains: vec!["www.facebook.com".to_string()],
+            registrable: vec!["fb.com".to_string()],
+            patterns: vec!["*.FBCDN.net".to_string()],
+        }
+    }
+
+    #[test]
+    fn matches_domains_registrable_domains_and_patterns() {
+        let allowlist = allowlist();
+
+        assert!(allowlist.contains("WWW.Facebook.com.", Some("facebook.com")));
+        assert!(allowlist.contains("login.fb.com", Some("fb.com")));
+        assert!(allowlist.contains("static.xx.fbcdn.net", Some("fbcdn.net")));
+
+        assert!(!allowlist.contains("facebook.com", Some("facebook.com")));
+        assert!(!allowlist.contains("fbcdn.net", Some("fbcdn.net")));
+        assert!(!allowlist.contains("login.fb.com", None));
+    }
+
+    #[test]
+    fn matches_wildcards_anywhere() {
+        assert!(wildcard_match(b"*", b""));
+        assert!(wildcard_match(b"a*c*e", b"abcde"));
+        assert!(!wildcard_match(b"a*c", b"abcd"));
+        assert!(!wildcard_match(b"", b"a"));
+    }
+}
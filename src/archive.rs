@@ -0,0 +1,361 @@
+use crate::errors::*;
+use chrono::Utc;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+// Longest a recorded message stays buffered in memory
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Never,
+    // Start a new file once this many (uncompressed) bytes were written
+    Size(u64),
+    // Start a new file every hour (UTC)
+    Hourly,
+    // Start a new file every day (UTC)
+    Daily,
+}
+
+// A file that is closed and replaced by a new one according to the rotation policy.
+// Files are named <prefix>-<UTC timestamp>-<sequence>.<extension>[.gz] and are only
+// rotated at the start of a line, so a record never spans two files.
+pub struct RotatingFile {
+    directory: PathBuf,
+    prefix: String,
+    extension: String,
+    rotation: Rotation,
+    compress: bool,
+    // Number of files kept, the oldest are removed on rotation
+    keep: Option<usize>,
+    writer: Option<Box<dyn Write + Send>>,
+    written: u64,
+    line_start: bool,
+    opened_period: String,
+    sequence: usize,
+}
+
+impl RotatingFile {
+    pub fn new<P: Into<PathBuf>>(
+        directory: P, prefix: &str, extension: &str, rotation: Rotation, compress: bool,
+    ) -> Result<Self> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory)
+            .chain_err(|| format!("Unable to create {}", directory.display()))?;
+
+        Ok(RotatingFile {
+            directory,
+            prefix: prefix.to_string(),
+            extension: extension.to_string(),
+            rotation,
+            compress,
+            keep: None,
+            writer: None,
+            written: 0,
+            line_start: true,
+            opened_period: String::new(),
+            sequence: 0,
+        })
+    }
+
+    pub fn set_retention(&mut self, keep: usize) {
+        self.keep = Some(keep.max(1));
+    }
+
+    fn should_rotate(&self) -> bool {
+        match self.rotation {
+            Rotation::Never => false,
+            Rotation::Size(max_size) => self.written >= max_size,
+            Rotation::Hourly | Rotation::Daily => self.current_period() != self.opened_period,
+        }
+    }
+
+    fn current_period(&self) -> String {
+        let format = match self.rotation {
+            Rotation::Daily => "%Y%m%d",
+            _ => "%Y%m%d%H",
+        };
+        Utc::now().format(format).to_string()
+    }
+
+    fn open(&mut self) -> Result<()> {
+        // Drop the current writer first so it is flushed (and gzip finished)
+        self.writer = None;
+
+        // Skips names left by an earlier run started within the same second
+        let timestamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let path = loop {
+            self.sequence += 1;
+
+            let mut file_name = format!(
+                "{}-{}-{:04}.{}",
+                self.prefix, timestamp, self.sequence, self.extension
+            );
+            if self.compress {
+                file_name.push_str(".gz");
+            }
+
+            let path = self.directory.join(file_name);
+            if !path.exists() {
+                break path;
+            }
+        };
+        let file = File::create(&path).chain_err(|| format!("Unable to create {}", path.display()))?;
+
+        self.writer = if self.compress {
+            Some(Box::new(BufWriter::new(GzEncoder::new(file, Compression::default()))))
+        } else {
+            Some(Box::new(BufWriter::new(file)))
+        };
+        self.written = 0;
+        self.opened_period = self.current_period();
+
+        if let Some(keep) = self.keep {
+            self.remove_old_files(keep)?;
+        }
+
+        Ok(())
+    }
+
+    // Oldest first by modification time, then by name as sequence numbers restart
+    // with each run
+    fn remove_old_files(&self, keep: usize) -> Result<()> {
+        let mut files: Vec<PathBuf> = fs::read_dir(&self.directory)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .map(|name| self.is_rotated_file(name))
+                    .unwrap_or(false)
+            })
+            .collect();
+        files.sort_by_key(|path| {
+            let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+            (modified, path.clone())
+        });
+
+        let excess = files.len().saturating_sub(keep);
+        for path in &files[..excess] {
+            fs::remove_file(path).chain_err(|| format!("Unable to remove {}", path.display()))?;
+        }
+
+        Ok(())
+    }
+
+    // Only names generated by open(), other files sharing the prefix, e.g.
+    // app-error.log next to app.log, are left alone
+    fn is_rotated_file(&self, name: &str) -> bool {
+        let extension = format!(".{}", self.extension);
+        let stamped = name
+            .strip_prefix(self.prefix.as_str())
+            .and_then(|rest| rest.strip_prefix('-'))
+            .and_then(|rest| rest.strip_suffix(".gz").or(Some(rest)))
+            .and_then(|rest| rest.strip_suffix(extension.as_str()));
+
+        // YYYYMMDDTHHMMSSZ-NNNN
+        let (timestamp, sequence) = match stamped.and_then(|rest| rest.split_once('-')) {
+            Some(parts) => parts,
+            None => return false,
+        };
+        let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+
+        timestamp.len() == 16
+            && timestamp.is_ascii()
+            && digits(&timestamp[..8])
+            && &timestamp[8..9] == "T"
+            && digits(&timestamp[9..15])
+            && &timestamp[15..] == "Z"
+            && sequence.len() >= 4
+            && digits(sequence)
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.writer.is_none() || (self.line_start && self.should_rotate()) {
+            self.open()
+                .map_err(|e| std::io::Error::other(e.to_string()))?;
+        }
+
+        let written = match self.writer {
+            Some(ref mut writer) => writer.write(buf)?,
+            None => 0,
+        };
+        self.written += written as u64;
+        if written > 0 {
+            self.line_start = buf[written - 1] == b'\n';
+        }
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.writer {
+            Some(ref mut writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+// Archives the raw certstream messages as NDJSON so they can be replayed later.
+// Buffered messages are written out every second, the current file is finished
+// (gzip trailer included) when the recorder is dropped.
+pub struct Recorder {
+    file: RotatingFile,
+    flushed: Instant,
+}
+
+impl Recorder {
+    pub fn new<P: Into<PathBuf>>(directory: P, rotation: Rotation, compress: bool) -> Result<Self> {
+        Ok(Recorder {
+            file: RotatingFile::new(directory, "certstream", "ndjson", rotation, compress)?,
+            flushed: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, message: &str) -> Result<()> {
+        // One message per line, certstream JSON is never pretty printed
+        let line = message.replace('\n', "");
+        self.file.write_all(line.as_bytes())?;
+        self.file.write_all(b"\n")?;
+
+        if self.flushed.elapsed() >= FLUSH_INTERVAL {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.file.flush()?;
+        self.flushed = Instant::now();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{open_input, read_domains};
+    use std::env;
+    use std::path::Path;
+
+    fn directory(name: &str) -> PathBuf {
+        let directory = env::temp_dir().join(format!("nettfiske-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn file_names(directory: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn recognises_rotated_file_names() {
+        let file =
+            RotatingFile::new(directory("names"), "app", "log", Rotation::Never, false).unwrap();
+
+        assert!(file.is_rotated_file("app-20260101T120000Z-0001.log"));
+        assert!(file.is_rotated_file("app-20260101T120000Z-12345.log.gz"));
+        assert!(!file.is_rotated_file("app-error.log"));
+        assert!(!file.is_rotated_file("app-20260101.log"));
+        assert!(!file.is_rotated_file("app-2026010é120000Z-0001.log"));
+        assert!(!file.is_rotated_file("app-20260101T120000Z-0001.txt"));
+        assert!(!file.is_rotated_file("application-20260101T120000Z-0001.log"));
+        fs::remove_dir_all(&file.directory).unwrap();
+    }
+
+    #[test]
+    fn keeps_foreign_files_on_retention() {
+        let directory = directory("retention");
+        let mut file =
+            RotatingFile::new(&directory, "app", "log", Rotation::Size(1), false).unwrap();
+        file.set_retention(2);
+        fs::write(directory.join("app-error.log"), "kept").unwrap();
+        fs::write(directory.join("app-20260101.log"), "kept").unwrap();
+
+        for line in 0..4 {
+            writeln!(file, "line {}", line).unwrap();
+        }
+        drop(file);
+
+        let names = file_names(&directory);
+        assert_eq!(names.len(), 4, "{:?}", names);
+        assert!(names.contains(&"app-error.log".to_string()));
+        assert!(names.contains(&"app-20260101.log".to_string()));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    fn lines(path: &Path) -> Vec<String> {
+        read_domains(open_input(path.to_str().unwrap()).unwrap())
+            .collect::<Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn rotates_on_size_at_line_boundaries() {
+        let directory = directory("size");
+        let mut recorder = Recorder::new(&directory, Rotation::Size(20), false).unwrap();
+
+        for message in &["{\"n\": 1}", "{\"n\": 2,\n \"long\": true}", "{\"n\": 3}"] {
+            recorder.record(message).unwrap();
+        }
+        drop(recorder);
+
+        let names = file_names(&directory);
+        assert_eq!(names.len(), 2, "{:?}", names);
+        assert_eq!(
+            lines(&directory.join(&names[0])),
+            vec!["{\"n\": 1}", "{\"n\": 2, \"long\": true}"]
+        );
+        assert_eq!(lines(&directory.join(&names[1])), vec!["{\"n\": 3}"]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn rotates_when_the_hour_changes() {
+        let directory = directory("hourly");
+        let mut file =
+            RotatingFile::new(&directory, "app", "log", Rotation::Hourly, false).unwrap();
+
+        writeln!(file, "first").unwrap();
+        writeln!(file, "same hour").unwrap();
+        assert_eq!(file_names(&directory).len(), 1);
+
+        file.opened_period = "2020010100".to_string();
+        writeln!(file, "next hour").unwrap();
+        drop(file);
+
+        assert_eq!(file_names(&directory).len(), 2);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn flushes_and_finishes_compressed_archives() {
+        let directory = directory("gzip");
+        let mut recorder = Recorder::new(&directory, Rotation::Never, true).unwrap();
+
+        recorder.record("{\"n\": 1}").unwrap();
+        recorder.record("{\"n\": 2}").unwrap();
+        recorder.flush().unwrap();
+        let name = file_names(&directory).remove(0);
+        assert!(name.ends_with(".ndjson.gz"), "{}", name);
+        assert!(fs::metadata(directory.join(&name)).unwrap().len() > 0);
+
+        drop(recorder);
+        assert_eq!(
+            lines(&directory.join(&name)),
+            vec!["{\"n\": 1}", "{\"n\": 2}"]
+        );
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,105 @@
+use rand::Rng;
+use std::time::Duration;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct BackoffConfig {
+    pub initial_delay_ms: u64,
+    pub max_delay_ms: u64,
+    pub multiplier: f64,
+    // Random spread applied to each delay, 0.2 means +/- 20%
+    pub jitter: f64,
+}
+
+impl Default for BackoffConfig {
+    fn default() -> Self {
+        BackoffConfig {
+            initial_delay_ms: 1000,
+            max_delay_ms: 60_000,
+            multiplier: 2.0,
+            jitter: 0.2,
+        }
+    }
+}
+
+// Exponential backoff with jitter
+pub struct Backoff {
+    config: BackoffConfig,
+    attempt: i32,
+}
+
+impl Backoff {
+    pub fn new(config: BackoffConfig) -> Self {
+        Backoff { config, attempt: 0 }
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let config = &self.config;
+        let base = config.initial_delay_ms as f64 * config.multiplier.powi(self.attempt);
+        let max_delay = config.max_delay_ms as f64;
+
+        let jitter = config.jitter.abs().min(1.0);
+        let factor = if jitter > 0.0 {
+            rand::thread_rng().gen_range(1.0 - jitter, 1.0 + jitter)
+        } else {
+            1.0
+        };
+
+        self.attempt = self.attempt.saturating_add(1);
+        // Capped after the jitter, so the delay never exceeds max_delay_ms
+        Duration::from_millis((base.min(max_delay) * factor).min(max_delay) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backoff(jitter: f64) -> Backoff {
+        Backoff::new(BackoffConfig {
+            initial_delay_ms: 100,
+            max_delay_ms: 1000,
+            multiplier: 2.0,
+            jitter,
+        })
+    }
+
+    fn delays(backoff: &mut Backoff, count: usize) -> Vec<u64> {
+        (0..count)
+            .map(|_| backoff.next_delay().as_millis() as u64)
+            .collect()
+    }
+
+    #[test]
+    fn doubles_up_to_the_maximum() {
+        let mut backoff = backoff(0.0);
+
+        assert_eq!(
+            delays(&mut backoff, 6),
+            vec![100, 200, 400, 800, 1000, 1000]
+        );
+
+        backoff.reset();
+        assert_eq!(delays(&mut backoff, 2), vec![100, 200]);
+    }
+
+    #[test]
+    fn keeps_jittered_delays_within_the_spread_and_the_maximum() {
+        let mut backoff = backoff(0.5);
+
+        for (attempt, delay) in delays(&mut backoff, 200).into_iter().enumerate() {
+            let base = (100 * 2u64.pow(attempt.min(4) as u32)).min(1000);
+            assert!(delay >= base / 2, "attempt {} waited {}ms", attempt, delay);
+            assert!(
+                delay <= (base * 3 / 2).min(1000),
+                "attempt {} waited {}ms",
+                attempt,
+                delay
+            );
+        }
+    }
+}
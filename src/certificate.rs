@@ -0,0 +1,187 @@
+use crate::allowlist::wildcard_match;
+use crate::data::Subject;
+
+// Legitimate certificate of an identity, names on a certificate matching one of
+// its profiles are not scored. Every field set has to match, a profile without
+// fields never matches. Fields are case-insensitive and '*' matches any characters.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct CertificateProfile {
+    // Subject O, OU and CN
+    pub organization: Option<String>,
+    pub organizational_unit: Option<String>,
+    pub common_name: Option<String>,
+    // Issuer O, CN or aggregated subject, e.g. "DigiCert*"
+    pub issuer: Option<String>,
+}
+
+impl CertificateProfile {
+    pub fn is_empty(&self) -> bool {
+        [
+            &self.organization,
+            &self.organizational_unit,
+            &self.common_name,
+            &self.issuer,
+        ]
+        .iter()
+        .all(|field| non_empty(field).is_none())
+    }
+
+    pub fn matches(&self, certificate: &CertificateSubjects) -> bool {
+        let subject = certificate.subject.as_ref();
+        let conditions = [
+            (
+                &self.organization,
+                subject.and_then(|s| s.organization.as_deref()),
+            ),
+            (
+                &self.organizational_unit,
+                subject.and_then(|s| s.organizational_unit.as_deref()),
+            ),
+            (
+                &self.common_name,
+                subject.and_then(|s| s.common_name.as_deref()),
+            ),
+        ];
+
+        let mut checked = false;
+
+        for (pattern, value) in conditions.iter() {
+            if let Some(pattern) = non_empty(pattern) {
+                checked = true;
+                match value {
+                    Some(value) if pattern_matches(pattern, value) => {}
+                    _ => return false,
+                }
+            }
+        }
+
+        if let Some(pattern) = non_empty(&self.issuer) {
+            checked = true;
+            match certificate.issuer {
+                Some(ref issuer) if issuer_matches(pattern, issuer) => {}
+                _ => return false,
+            }
+        }
+
+        checked
+    }
+}
+
+// Subject and issuer of an observed certificate, either may be unknown
+#[derive(Debug, Clone, Default)]
+pub struct CertificateSubjects {
+    pub subject: Option<Subject>,
+    pub issuer: Option<Subject>,
+}
+
+pub fn pattern_matches(pattern: &str, value: &str) -> bool {
+    wildcard_match(
+        pattern.trim().to_lowercase().as_bytes(),
+        value.trim().to_lowercase().as_bytes(),
+    )
+}
+
+// Matches the issuer O, CN or the aggregated subject
+pub fn issuer_matches(pattern: &str, issuer: &Subject) -> bool {
+    [
+        issuer.organization.as_deref(),
+        issuer.common_name.as_deref(),
+        Some(issuer.aggregated.as_str()),
+    ]
+    .iter()
+    .flatten()
+    .any(|field| !field.is_empty() && pattern_matches(pattern, field))
+}
+
+// Empty strings in older configs mean the field is not set
+fn non_empty(field: &Option<String>) -> Option<&str> {
+    field.as_deref().map(str::trim).filter(|f| !f.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subject(organization: &str, common_name: &str) -> Option<Subject> {
+        Some(Subject {
+            aggregated: format!("/O={}/CN={}", organization, common_name),
+            organization: Some(organization.to_string()),
+            common_name: Some(common_name.to_string()),
+            ..Subject::default()
+        })
+    }
+
+    fn paypal_certificate() -> CertificateSubjects {
+        CertificateSubjects {
+            subject: subject("PayPal, Inc.", "www.paypal.com"),
+            issuer: subject(
+                "DigiCert Inc",
+                "DigiCert SHA2 Extended Validation Server CA",
+            ),
+        }
+    }
+
+    #[test]
+    fn matches_fields_case_insensitively_with_wildcards() {
+        let profile = CertificateProfile {
+            organization: Some("paypal, inc.".to_string()),
+            common_name: Some("*.PAYPAL.COM".to_string()),
+            issuer: Some("digicert*".to_string()),
+            ..CertificateProfile::default()
+        };
+
+        assert!(profile.matches(&paypal_certificate()));
+    }
+
+    #[test]
+    fn every_field_set_has_to_match() {
+        let profile = CertificateProfile {
+            organization: Some("PayPal, Inc.".to_string()),
+            issuer: Some("Let's Encrypt".to_string()),
+            ..CertificateProfile::default()
+        };
+
+        assert!(!profile.matches(&paypal_certificate()));
+    }
+
+    #[test]
+    fn matches_the_issuer_subject() {
+        let profile = CertificateProfile {
+            issuer: Some("*/CN=DigiCert SHA2 *".to_string()),
+            ..CertificateProfile::default()
+        };
+
+        assert!(profile.matches(&paypal_certificate()));
+    }
+
+    #[test]
+    fn a_profile_without_fields_never_matches() {
+        let profile = CertificateProfile {
+            organization: Some(" ".to_string()),
+            ..CertificateProfile::default()
+        };
+
+        assert!(profile.is_empty());
+        assert!(!profile.matches(&paypal_certificate()));
+        assert!(!CertificateProfile::default().matches(&paypal_certificate()));
+    }
+
+    #[test]
+    fn missing_certificate_data_does_not_match() {
+        let profile = CertificateProfile {
+            organization: Some("PayPal, Inc.".to_string()),
+            ..CertificateProfile::default()
+        };
+        let issuer_only = CertificateProfile {
+            issuer: Some("DigiCert*".to_string()),
+            ..CertificateProfile::default()
+        };
+
+        assert!(!profile.matches(&CertificateSubjects::default()));
+        assert!(!issuer_only.matches(&CertificateSubjects {
+            subject: subject("PayPal, Inc.", "www.paypal.com"),
+            issuer: None,
+        }));
+    }
+}
@@ -1,3 +1,16 @@
+use crate::allowlist::Allowlist;
+use crate::backoff::BackoffConfig;
+use crate::certificate::CertificateProfile;
+use crate::dedup::DedupConfig;
+use crate::issuer::IssuerRule;
+use crate::logging::LogConfig;
+use crate::sink::{default_sinks, LegacySink, SinkConfig, SinkKind};
+use crate::syslog::SyslogConfig;
+use crate::verdict::Severity;
+use crate::webhook::WebhookConfig;
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+
 #[derive(Deserialize, Debug)]
 pub struct CertString {
     pub message_type: String,
@@ -8,12 +21,44 @@ pub struct CertString {
 pub struct Data {
     pub leaf_cert: LeafCert,
     pub chain: Vec<ChainObjects>,
+    // Position of the entry in the CT log
+    pub cert_index: Option<u64>,
+    // get-entries URL of the entry in the CT log
+    pub cert_link: Option<String>,
+    // When certstream saw the entry, seconds since the epoch
+    pub seen: Option<f64>,
+    pub source: Option<Source>,
+    // X509LogEntry or PrecertLogEntry
+    pub update_type: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Source {
+    pub name: String,
+    pub url: String,
 }
 
 #[derive(Deserialize, Debug)]
 pub struct LeafCert {
     pub subject: Subject,
+    pub issuer: Option<Subject>,
     pub all_domains: Vec<String>,
+    // Extension name to its textual value, e.g. "subjectAltName" or "keyUsage"
+    #[serde(default)]
+    pub extensions: BTreeMap<String, serde_json::Value>,
+    pub fingerprint: Option<String>,
+    pub serial_number: Option<String>,
+    pub signature_algorithm: Option<String>,
+    // Validity period, seconds since the epoch
+    #[serde(default, deserialize_with = "timestamp")]
+    pub not_before: Option<i64>,
+    #[serde(default, deserialize_with = "timestamp")]
+    pub not_after: Option<i64>,
+}
+
+// Certstream sends whole seconds as floats, e.g. 1509598061.0
+fn timestamp<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<i64>, D::Error> {
+    Ok(Option::<f64>::deserialize(deserializer)?.map(|seconds| seconds as i64))
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -21,15 +66,16 @@ pub struct ChainObjects {
     pub subject: Subject,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Default)]
 pub struct Subject {
-    aggregated: String,
+    #[serde(default)]
+    pub aggregated: String,
     #[serde(rename = "C")]
-    c: Option<String>,
+    pub c: Option<String>,
     #[serde(rename = "ST")]
-    st: Option<String>,
+    pub st: Option<String>,
     #[serde(rename = "L")]
-    l: Option<String>,
+    pub l: Option<String>,
     #[serde(rename = "O")]
     pub organization: Option<String>,
     #[serde(rename = "OU")]
@@ -42,24 +88,355 @@ pub struct Subject {
 #[derive(Deserialize, Debug, Clone)]
 pub struct Config {
     pub identities: Vec<WebsiteIdentity>,
+    #[serde(default)]
+    pub certstream: CertStreamConfig,
+    #[serde(default)]
+    pub scoring: ScoringConfig,
+    // Domains skipped for every identity
+    #[serde(default)]
+    pub allowlist: Allowlist,
+    #[serde(default)]
+    pub public_suffix: PublicSuffixConfig,
+    #[serde(default)]
+    pub dedup: DedupConfig,
+    // Where the findings are reported, the console and the log file by default
+    #[serde(default = "default_sinks")]
+    pub sinks: Vec<SinkConfig>,
+    #[serde(default)]
+    pub log: LogConfig,
+    // Deprecated, moved into sinks by migrate_legacy_sinks
+    #[serde(default)]
+    pub webhook: Option<LegacySink<WebhookConfig>>,
+    #[serde(default)]
+    pub syslog: Option<LegacySink<SyslogConfig>>,
+}
+
+impl Config {
+    // Moves the top level webhook and syslog settings of older configs into the
+    // sinks list and returns the keys that were moved
+    pub fn migrate_legacy_sinks(&mut self) -> Vec<&'static str> {
+        let mut moved = Vec::new();
+
+        if let Some(webhook) = self.webhook.take() {
+            self.sinks
+                .push(SinkConfig::from_legacy(webhook, SinkKind::Webhook));
+            moved.push("webhook");
+        }
+        if let Some(syslog) = self.syslog.take() {
+            self.sinks
+                .push(SinkConfig::from_legacy(syslog, SinkKind::Syslog));
+            moved.push("syslog");
+        }
+
+        moved
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct PublicSuffixConfig {
+    // Local copy of the list, the bundled snapshot is used when not set
+    pub path: Option<String>,
+    // Download a fresh list periodically, disabled when not set
+    pub refresh_hours: Option<u64>,
+    // Where to download the list from, publicsuffix.org by default
+    pub url: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ScoringConfig {
+    // Points for a label containing an identity keyword
+    pub keyword_points: usize,
+    pub registrable_keyword_weight: usize,
+    pub subdomain_keyword_weight: usize,
+    // Points for a label one edit away from an identity keyword
+    pub edit_distance_points: usize,
+    // Points for a punycode label zero or one edit away from an identity keyword
+    pub homoglyph_edit_distance_points: usize,
+    pub registrable_edit_distance_weight: usize,
+    pub subdomain_edit_distance_weight: usize,
+    pub tld_on_subdomain_weight: usize,
+    // Domains with at least nested_min_labels labels get nested_label_points per label
+    pub nested_label_points: usize,
+    pub nested_min_labels: usize,
+    // Points for a wildcard certificate under a registrable domain imitating an identity
+    pub wildcard_lookalike_points: usize,
+    // Points per additional certificate name containing a brand keyword
+    pub branded_names_points: usize,
+    // Points for a certificate whose names imitate more than one identity
+    pub mixed_brands_points: usize,
+    // Certificate level rules on the issuer, validity and subject
+    pub issuer_rules: Vec<IssuerRule>,
+    pub tiers: Vec<SeverityTier>,
+}
+
+impl Default for ScoringConfig {
+    fn default() -> Self {
+        ScoringConfig {
+            keyword_points: 10,
+            registrable_keyword_weight: 4,
+            subdomain_keyword_weight: 5,
+            edit_distance_points: 8,
+            homoglyph_edit_distance_points: 15,
+            registrable_edit_distance_weight: 6,
+            subdomain_edit_distance_weight: 4,
+            tld_on_subdomain_weight: 4,
+            nested_label_points: 3,
+            nested_min_labels: 3,
+            wildcard_lookalike_points: 20,
+            branded_names_points: 10,
+            mixed_brands_points: 20,
+            issuer_rules: Vec::new(),
+            tiers: default_tiers(),
+        }
+    }
+}
+
+impl ScoringConfig {
+    // The most severe tier reached by the score, if any
+    pub fn tier(&self, score: usize, punycode_detected: bool) -> Option<&SeverityTier> {
+        self.tiers
+            .iter()
+            .filter(|tier| score >= tier.threshold)
+            .filter(|tier| punycode_detected || !tier.requires_punycode)
+            .max_by_key(|tier| tier.severity)
+    }
+
+    pub fn severity(&self, score: usize, punycode_detected: bool) -> Severity {
+        self.tier(score, punycode_detected)
+            .map(|tier| tier.severity)
+            .unwrap_or(Severity::None)
+    }
+
+    pub fn tier_for(&self, severity: Severity) -> Option<&SeverityTier> {
+        self.tiers.iter().find(|tier| tier.severity == severity)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SeverityTier {
+    pub severity: Severity,
+    pub threshold: usize,
+    // Console style, e.g. "yellow" or "red.on_black.bold"
+    #[serde(default)]
+    pub colour: String,
+    // Only reached when the domain contains punycode
+    #[serde(default)]
+    pub requires_punycode: bool,
+    // Print the domain as it appears on the certificate instead of decoded
+    #[serde(default)]
+    pub show_original: bool,
+    // Names of the sinks the finding is sent to, all of them when not set
+    #[serde(default)]
+    pub sinks: Option<Vec<String>>,
+}
+
+impl SeverityTier {
+    pub fn has_sink(&self, sink: &str) -> bool {
+        match self.sinks {
+            Some(ref sinks) => sinks.iter().any(|s| s == sink),
+            None => true,
+        }
+    }
+}
+
+fn default_tiers() -> Vec<SeverityTier> {
+    let tier = |severity, threshold, colour: &str, requires_punycode, show_original| SeverityTier {
+        severity,
+        threshold,
+        colour: colour.to_string(),
+        requires_punycode,
+        show_original,
+        sinks: None,
+    };
+
+    vec![
+        tier(Severity::Low, 56, "magenta", false, true),
+        tier(Severity::Medium, 70, "yellow", false, false),
+        tier(Severity::High, 90, "red", false, false),
+        tier(Severity::Critical, 90, "red.on_black.bold", true, false),
+    ]
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CertStreamConfig {
+    pub url: String,
+    // Extra headers sent with the websocket handshake, e.g. authentication
+    pub headers: BTreeMap<String, String>,
+    pub tls: TlsConfig,
+    pub reconnect: BackoffConfig,
+    // Reconnect when no message was received for this long, 0 disables it.
+    // Pongs don't count, a stalled feed can still answer pings.
+    pub idle_timeout_secs: u64,
+    // Send a ping this often to keep the connection alive, 0 disables it
+    pub ping_interval_secs: u64,
+}
+
+impl Default for CertStreamConfig {
+    fn default() -> Self {
+        CertStreamConfig {
+            url: "wss://certstream.calidog.io".to_string(),
+            headers: BTreeMap::new(),
+            tls: TlsConfig::default(),
+            reconnect: BackoffConfig::default(),
+            idle_timeout_secs: 60,
+            ping_interval_secs: 30,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct TlsConfig {
+    // PEM encoded CA certificate to trust, e.g. for a self-hosted certstream-server
+    pub ca_certificate: Option<String>,
+    pub accept_invalid_certs: bool,
+    pub accept_invalid_hostnames: bool,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct WebsiteIdentity {
     pub common_name: String,
-    #[serde(default = "default_certificate")]
-    pub certificate: Certificate,
+    // Legitimate certificates of the identity, see CertificateProfile
+    #[serde(default)]
+    pub certificates: Vec<CertificateProfile>,
+    // Single legitimate certificate, older form of certificates
+    #[serde(default)]
+    pub certificate: Option<Certificate>,
+    // Extra brand keywords, e.g. "pypl" and "paypa1" for "paypal"
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    // Multiplier applied to the points scored against this identity
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+    // Subdomain labels containing any of these are not compared by edit distance
+    #[serde(default = "default_ignore_labels")]
+    pub ignore_labels: Vec<String>,
+    // Registrable domains owned by the identity, never scored against it
+    #[serde(default)]
+    pub domains: Vec<String>,
+    // Further domains never scored against the identity
+    #[serde(default)]
+    pub allowlist: Allowlist,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+impl WebsiteIdentity {
+    pub fn keywords(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.common_name.as_str()).chain(self.keywords.iter().map(|k| k.as_str()))
+    }
+
+    pub fn weighted(&self, points: usize) -> usize {
+        (points as f64 * self.weight).round() as usize
+    }
+
+    pub fn ignores(&self, label: &str) -> bool {
+        self.ignore_labels
+            .iter()
+            .any(|ignored| label.contains(ignored.as_str()))
+    }
+
+    pub fn profiles(&self) -> impl Iterator<Item = CertificateProfile> + '_ {
+        self.certificate
+            .iter()
+            .map(CertificateProfile::from)
+            .chain(self.certificates.iter().cloned())
+    }
+
+    pub fn owns(&self, domain: &str, registrable: Option<&str>) -> bool {
+        if let Some(registrable) = registrable {
+            if self
+                .domains
+                .iter()
+                .any(|d| d.eq_ignore_ascii_case(registrable))
+            {
+                return true;
+            }
+        }
+
+        self.allowlist.contains(domain, registrable)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
 pub struct Certificate {
-    pub issued_to: String,
-    pub issued_by: String,
+    // Subject organisation
+    pub issued_to: Option<String>,
+    // Issuer organisation
+    pub issued_by: Option<String>,
+}
+
+impl From<&Certificate> for CertificateProfile {
+    fn from(certificate: &Certificate) -> Self {
+        CertificateProfile {
+            organization: certificate.issued_to.clone(),
+            issuer: certificate.issued_by.clone(),
+            ..CertificateProfile::default()
+        }
+    }
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+fn default_ignore_labels() -> Vec<String> {
+    vec!["mail".to_string(), "cloud".to_string()]
 }
 
-fn default_certificate() -> Certificate {
-    Certificate {
-        issued_to: "".to_string(),
-        issued_by: "".to_string(),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_certstream_frame() {
+        let frame = include_str!("../tests/fixtures/certificate_update.json");
+        let message: CertString = serde_json::from_str(frame).unwrap();
+        let leaf_cert = &message.data.leaf_cert;
+
+        assert_eq!(message.message_type, "certificate_update");
+        assert_eq!(
+            leaf_cert.all_domains,
+            ["paypal-login.com", "www.paypal-login.com"]
+        );
+        assert_eq!(leaf_cert.not_before, Some(1_509_598_061));
+        assert_eq!(leaf_cert.not_after, Some(1_517_374_061));
+        assert_eq!(message.data.cert_index, Some(19_587_936));
+        assert_eq!(
+            message.data.chain[0].subject.organization.as_deref(),
+            Some("Let's Encrypt")
+        );
+    }
+
+    #[test]
+    fn moves_top_level_webhook_and_syslog_into_sinks() {
+        let mut config: Config = serde_json::from_str(
+            r#"{"identities": [{"common_name": "paypal"}],
+                "webhook": {"url": "http://localhost/", "min_severity": "high"},
+                "syslog": {"transport": "tcp"}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.migrate_legacy_sinks(), ["webhook", "syslog"]);
+        assert!(config.webhook.is_none() && config.syslog.is_none());
+
+        let names: Vec<&str> = config.sinks.iter().map(|sink| sink.name()).collect();
+        assert_eq!(names, ["stdout", "log", "webhook", "syslog"]);
+        assert_eq!(config.sinks[2].filter.min_severity, Severity::High);
+        assert_eq!(config.sinks[3].filter.min_severity, Severity::Low);
+        assert!(config.migrate_legacy_sinks().is_empty());
+    }
+
+    #[test]
+    fn accepts_integer_and_missing_timestamps() {
+        let leaf_cert: LeafCert = serde_json::from_str(
+            r#"{"subject": {"CN": "a.com"}, "all_domains": ["a.com"], "not_before": 1509598061}"#,
+        )
+        .unwrap();
+
+        assert_eq!(leaf_cert.not_before, Some(1_509_598_061));
+        assert_eq!(leaf_cert.not_after, None);
     }
 }
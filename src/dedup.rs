@@ -0,0 +1,159 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DedupConfig {
+    // A domain is reported at most once per window, 0 disables deduplication
+    pub window_secs: u64,
+    // Maximum number of domains remembered
+    pub capacity: usize,
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        DedupConfig {
+            window_secs: 3600,
+            capacity: 100_000,
+        }
+    }
+}
+
+struct Entry {
+    reported: Instant,
+    duplicates: usize,
+}
+
+// Remembers recently reported domains. Certstream emits the precertificate and the
+// final certificate, and many certificates share names, so the same domain shows
+// up repeatedly within minutes.
+pub struct DedupCache {
+    window: Duration,
+    capacity: usize,
+    entries: HashMap<String, Entry>,
+    // Keys in the order they were (re)reported, oldest first
+    order: VecDeque<(String, Instant)>,
+}
+
+impl DedupCache {
+    pub fn new(config: &DedupConfig) -> Self {
+        DedupCache {
+            window: Duration::from_secs(config.window_secs),
+            capacity: config.capacity.max(1),
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    // Returns None when the domain was already reported within the window. Otherwise
+    // returns how many times it was seen since it was last reported.
+    pub fn check(&mut self, domain: &str) -> Option<usize> {
+        if self.window == Duration::from_secs(0) {
+            return Some(0);
+        }
+
+        let key = normalise(domain);
+        let now = Instant::now();
+
+        if let Some(entry) = self.entries.get_mut(&key) {
+            if now.duration_since(entry.reported) < self.window {
+                entry.duplicates += 1;
+                return None;
+            }
+
+            let duplicates = entry.duplicates;
+            entry.reported = now;
+            entry.duplicates = 0;
+            self.order.push_back((key, now));
+            return Some(duplicates);
+        }
+
+        self.evict();
+        self.entries.insert(
+            key.clone(),
+            Entry {
+                reported: now,
+                duplicates: 0,
+            },
+        );
+        self.order.push_back((key, now));
+
+        Some(0)
+    }
+
+    // Drops the oldest domains once the cache is full
+    fn evict(&mut self) {
+        while self.entries.len() >= self.capacity {
+            let (key, reported) = match self.order.pop_front() {
+                Some(oldest) => oldest,
+                None => break,
+            };
+
+            // Skip stale positions of domains reported again since
+            let current = self.entries.get(&key).map(|entry| entry.reported);
+            if current == Some(reported) {
+                self.entries.remove(&key);
+            }
+        }
+    }
+}
+
+fn normalise(domain: &str) -> String {
+    domain
+        .trim()
+        .trim_start_matches("*.")
+        .trim_end_matches('.')
+        .to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn cache(window: Duration, capacity: usize) -> DedupCache {
+        DedupCache {
+            window,
+            capacity,
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    #[test]
+    fn reports_a_domain_once_per_window() {
+        let mut cache = cache(Duration::from_millis(100), 10);
+
+        assert_eq!(cache.check("paypal-login.com"), Some(0));
+        assert_eq!(cache.check("*.PayPal-Login.com."), None);
+        assert_eq!(cache.check("paypal-login.com"), None);
+
+        thread::sleep(Duration::from_millis(150));
+        assert_eq!(cache.check("paypal-login.com"), Some(2));
+        assert_eq!(cache.check("paypal-login.com"), None);
+    }
+
+    #[test]
+    fn zero_window_disables_deduplication() {
+        let mut cache = DedupCache::new(&DedupConfig {
+            window_secs: 0,
+            ..DedupConfig::default()
+        });
+
+        assert_eq!(cache.check("paypal-login.com"), Some(0));
+        assert_eq!(cache.check("paypal-login.com"), Some(0));
+    }
+
+    #[test]
+    fn forgets_the_oldest_domains_when_full() {
+        let mut cache = cache(Duration::from_secs(60), 2);
+
+        cache.check("a.com");
+        cache.check("b.com");
+        cache.check("c.com");
+
+        assert_eq!(cache.check("a.com"), Some(0));
+        assert_eq!(cache.check("c.com"), None);
+        assert_eq!(cache.entries.len(), 2);
+    }
+}
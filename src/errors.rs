@@ -1,6 +1,5 @@
-use std;
-use url;
-use tungstenite;
+// error_chain 0.12 checks a cfg set by its own build script
+#![allow(unexpected_cfgs)]
 
 error_chain! {
     types {
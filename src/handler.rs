@@ -0,0 +1,58 @@
+use crate::errors::*;
+use crate::websockets::EventHandler;
+use crate::nettfiske::Nettfiske;
+use crate::data::CertString;
+use serde_json::from_str;
+
+pub struct CertStreamHandler {
+    nettfiske: Nettfiske,
+}
+
+impl CertStreamHandler {
+    pub fn new(nettfiske: Nettfiske) -> Self {
+        CertStreamHandler { nettfiske }
+    }
+
+    pub fn nettfiske(&self) -> &Nettfiske {
+        &self.nettfiske
+    }
+}
+
+impl EventHandler for CertStreamHandler {
+    fn on_connect(&mut self) {
+        info!("Connected to certstream ({})", self.nettfiske.metrics());
+    }
+
+    fn on_data_event(&mut self, event: String) {
+        match from_str(&event) {
+            Ok(message) => {
+                let cert: CertString = message;
+                if cert.message_type.contains("certificate_update") {
+                    let mut finding = self.nettfiske.analyse_certificate(&cert.data);
+                    if finding.is_suspicious() && self.nettfiske.deduplicate(&mut finding) {
+                        self.nettfiske.report(&finding);
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Received unknown message: {}", e);
+            }
+        }
+    }
+
+    fn on_error(&mut self, message: Error) {
+        display(format!("<<< Error<{}>", message));
+    }
+
+    fn on_disconnect(&mut self) {
+        warn!(
+            "Disconnected from certstream ({})",
+            self.nettfiske.metrics()
+        );
+    }
+}
+
+// On stderr, stdout may carry JSON findings
+fn display(string: String) {
+    eprintln!("{}", string);
+}
@@ -0,0 +1,126 @@
+use crate::errors::*;
+use crate::websockets::EventHandler;
+use flate2::bufread::MultiGzDecoder;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+static GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+// Opens a file for line based reading, "-" reads from stdin.
+// Gzip compressed input is detected and decompressed transparently.
+pub fn open_input(path: &str) -> Result<Box<dyn BufRead>> {
+    let mut reader: Box<dyn BufRead> = if path == "-" {
+        Box::new(BufReader::new(io::stdin()))
+    } else {
+        let file = File::open(path).chain_err(|| format!("Unable to open {}", path))?;
+        Box::new(BufReader::new(file))
+    };
+
+    if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        return Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))));
+    }
+
+    Ok(reader)
+}
+
+// Iterates over the domains of a list, one per line. Blank lines and lines
+// starting with '#' are skipped.
+pub fn read_domains(reader: Box<dyn BufRead>) -> impl Iterator<Item = Result<String>> {
+    reader
+        .lines()
+        .map(|line| line.map(|l| l.trim().to_string()).map_err(Error::from))
+        .filter(|line| match line {
+            Ok(domain) => !domain.is_empty() && !domain.starts_with('#'),
+            Err(_) => true,
+        })
+}
+
+// Feeds recorded certstream messages, one JSON message per line, to the handler
+// as if they were received from the socket. Returns the number of messages.
+pub fn replay<H: EventHandler>(reader: Box<dyn BufRead>, handler: &mut H) -> Result<usize> {
+    let mut messages = 0;
+
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        handler.on_data_event(line);
+        messages += 1;
+    }
+
+    Ok(messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+
+    #[derive(Default)]
+    struct Recorder {
+        messages: Vec<String>,
+    }
+
+    impl EventHandler for Recorder {
+        fn on_connect(&mut self) {}
+
+        fn on_data_event(&mut self, event: String) {
+            self.messages.push(event);
+        }
+
+        fn on_error(&mut self, message: Error) {
+            panic!("{}", message);
+        }
+    }
+
+    static RECORDING: &str =
+        "{\"message_type\": \"heartbeat\"}\n\n  \n{\"message_type\": \"certificate_update\"}\n";
+
+    fn replay_file(name: &str, contents: &[u8]) -> Recorder {
+        let path = env::temp_dir().join(format!("nettfiske-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+
+        let mut recorder = Recorder::default();
+        let messages = replay(open_input(path.to_str().unwrap()).unwrap(), &mut recorder).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(messages, recorder.messages.len());
+        recorder
+    }
+
+    #[test]
+    fn replays_plain_recordings() {
+        let recorder = replay_file("plain.ndjson", RECORDING.as_bytes());
+
+        assert_eq!(recorder.messages.len(), 2);
+        assert_eq!(
+            recorder.messages[1],
+            "{\"message_type\": \"certificate_update\"}"
+        );
+    }
+
+    #[test]
+    fn replays_gzip_recordings() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(RECORDING.as_bytes()).unwrap();
+
+        let recorder = replay_file("compressed.ndjson.gz", &encoder.finish().unwrap());
+
+        assert_eq!(recorder.messages.len(), 2);
+    }
+
+    #[test]
+    fn skips_comments_and_blank_lines_in_domain_lists() {
+        let list: &[u8] = b"# lookalikes\npaypal-login.com\n\n  secure-paypa1.com  \n";
+
+        let domains: Vec<String> = read_domains(Box::new(list)).collect::<Result<_>>().unwrap();
+
+        assert_eq!(domains, vec!["paypal-login.com", "secure-paypa1.com"]);
+    }
+}
@@ -0,0 +1,184 @@
+use crate::certificate::issuer_matches;
+use crate::data::{LeafCert, Subject};
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+// Certificate level scoring rule, e.g. free DV issuers or short validity periods.
+// Every condition set on the rule has to hold, a rule without conditions never
+// applies. The points are only added to certificates with a matching name.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct IssuerRule {
+    // Shown in the score breakdown, e.g. "free_dv"
+    pub name: String,
+    pub points: usize,
+    // Issuer O, CN or aggregated subject, case-insensitive, '*' matches any
+    // characters, e.g. "Let's Encrypt" or "cPanel*"
+    pub issuers: Vec<String>,
+    // Validity period no longer than this
+    pub max_validity_days: Option<u64>,
+    // Subject without (true) or with (false) an organisation
+    pub missing_subject_organization: Option<bool>,
+}
+
+impl IssuerRule {
+    pub fn has_conditions(&self) -> bool {
+        !self.issuers.is_empty()
+            || self.max_validity_days.is_some()
+            || self.missing_subject_organization.is_some()
+    }
+
+    // The issuer is resolved by the caller, certstream often leaves it out of the
+    // leaf certificate and it is then the first certificate of the chain
+    pub fn applies(&self, leaf_cert: &LeafCert, issuer: Option<&Subject>) -> bool {
+        if !self.has_conditions() {
+            return false;
+        }
+
+        if !self.issuers.is_empty() {
+            match issuer {
+                Some(issuer) if self.issuer_matches(issuer) => {}
+                _ => return false,
+            }
+        }
+
+        if let Some(max_days) = self.max_validity_days {
+            match validity_days(leaf_cert) {
+                Some(days) if days <= max_days => {}
+                _ => return false,
+            }
+        }
+
+        if let Some(missing) = self.missing_subject_organization {
+            let organization = leaf_cert
+                .subject
+                .organization
+                .as_deref()
+                .map(str::trim)
+                .unwrap_or_default();
+            if organization.is_empty() != missing {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn issuer_matches(&self, issuer: &Subject) -> bool {
+        self.issuers
+            .iter()
+            .any(|pattern| issuer_matches(pattern, issuer))
+    }
+}
+
+// Rounded up, e.g. Let's Encrypt certificates end one second short of 90 days
+pub fn validity_days(leaf_cert: &LeafCert) -> Option<u64> {
+    match (leaf_cert.not_before, leaf_cert.not_after) {
+        (Some(not_before), Some(not_after)) if not_after >= not_before => {
+            let seconds = not_after - not_before;
+            Some(((seconds + SECONDS_PER_DAY - 1) / SECONDS_PER_DAY) as u64)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Let's Encrypt certificate, 90 days minus one second
+    fn leaf_cert(organization: Option<&str>) -> LeafCert {
+        LeafCert {
+            subject: Subject {
+                organization: organization.map(str::to_string),
+                ..Subject::default()
+            },
+            issuer: Some(Subject {
+                aggregated: "/C=US/O=Let's Encrypt/CN=R3".to_string(),
+                organization: Some("Let's Encrypt".to_string()),
+                common_name: Some("R3".to_string()),
+                ..Subject::default()
+            }),
+            all_domains: vec!["paypal-login.com".to_string()],
+            extensions: Default::default(),
+            fingerprint: None,
+            serial_number: None,
+            signature_algorithm: None,
+            not_before: Some(1_600_000_000),
+            not_after: Some(1_600_000_000 + 90 * SECONDS_PER_DAY - 1),
+        }
+    }
+
+    fn applies(rule: &IssuerRule, leaf_cert: &LeafCert) -> bool {
+        rule.applies(leaf_cert, leaf_cert.issuer.as_ref())
+    }
+
+    fn rule() -> IssuerRule {
+        IssuerRule {
+            name: "free_dv".to_string(),
+            points: 10,
+            ..IssuerRule::default()
+        }
+    }
+
+    #[test]
+    fn rounds_the_validity_period_up() {
+        let mut leaf_cert = leaf_cert(None);
+        assert_eq!(validity_days(&leaf_cert), Some(90));
+
+        leaf_cert.not_after = Some(1_600_000_000);
+        assert_eq!(validity_days(&leaf_cert), Some(0));
+
+        leaf_cert.not_after = Some(1_500_000_000);
+        assert_eq!(validity_days(&leaf_cert), None);
+
+        leaf_cert.not_after = None;
+        assert_eq!(validity_days(&leaf_cert), None);
+    }
+
+    #[test]
+    fn applies_when_every_condition_holds() {
+        let rule = IssuerRule {
+            issuers: vec!["let's encrypt".to_string(), "cPanel*".to_string()],
+            max_validity_days: Some(90),
+            missing_subject_organization: Some(true),
+            ..rule()
+        };
+
+        assert!(applies(&rule, &leaf_cert(None)));
+        assert!(applies(&rule, &leaf_cert(Some(" "))));
+        assert!(!applies(&rule, &leaf_cert(Some("PayPal, Inc."))));
+
+        let shorter = IssuerRule {
+            max_validity_days: Some(89),
+            ..rule.clone()
+        };
+        assert!(!applies(&shorter, &leaf_cert(None)));
+
+        let other_issuer = IssuerRule {
+            issuers: vec!["DigiCert*".to_string()],
+            ..rule
+        };
+        assert!(!applies(&other_issuer, &leaf_cert(None)));
+    }
+
+    #[test]
+    fn needs_the_certificate_data_it_checks() {
+        let rule = IssuerRule {
+            issuers: vec!["Let's Encrypt".to_string()],
+            max_validity_days: Some(90),
+            ..rule()
+        };
+        let mut undated = leaf_cert(None);
+        undated.not_before = None;
+        assert!(!applies(&rule, &undated));
+
+        assert!(!rule.applies(&leaf_cert(None), None));
+    }
+
+    #[test]
+    fn a_rule_without_conditions_never_applies() {
+        assert!(!rule().has_conditions());
+        assert!(!applies(&rule(), &leaf_cert(None)));
+    }
+}
@@ -0,0 +1,36 @@
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate error_chain;
+#[macro_use]
+extern crate serde_derive;
+
+pub mod allowlist;
+pub mod archive;
+pub mod backoff;
+pub mod certificate;
+pub mod data;
+pub mod dedup;
+pub mod errors;
+pub mod handler;
+pub mod input;
+pub mod issuer;
+pub mod logging;
+pub mod metrics;
+pub mod net;
+pub mod nettfiske;
+pub mod output;
+pub mod shutdown;
+pub mod sink;
+pub mod suffix;
+pub mod syslog;
+pub mod validate;
+pub mod verdict;
+pub mod webhook;
+pub mod websockets;
+
+pub use crate::data::{CertString, Config, WebsiteIdentity};
+pub use crate::handler::CertStreamHandler;
+pub use crate::nettfiske::Nettfiske;
+pub use crate::sink::{Sink, SinkFilter};
+pub use crate::verdict::{Finding, Severity, Signal, SignalKind, Verdict};
@@ -0,0 +1,207 @@
+use crate::archive::{RotatingFile, Rotation};
+use crate::errors::*;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, SecondsFormat, TimeZone, Utc};
+use log::LevelFilter;
+use serde_json::json;
+use std::path::Path;
+use std::str::FromStr;
+
+const DEFAULT_TIMESTAMP: &str = "[%Y-%m-%d][%H:%M:%S]";
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    // "<timestamp> <message>"
+    Plain,
+    // One JSON object per line with timestamp, level, target and message
+    Json,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogInterval {
+    Hourly,
+    Daily,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct LogRotation {
+    // Start a new file once it reaches this size
+    pub max_size_mb: Option<u64>,
+    // Or start a new file every hour or day (UTC)
+    pub interval: Option<LogInterval>,
+    // Number of log files kept, all of them when not set
+    pub keep: Option<usize>,
+    pub compress: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LogConfig {
+    // Rotated files are named <stem>-<UTC timestamp>-<sequence>.<extension>
+    pub path: String,
+    // error, warn, info, debug or trace
+    pub level: String,
+    // chrono format string, or "rfc3339"
+    pub timestamp: String,
+    // Timestamps in UTC instead of the local time zone
+    pub utc: bool,
+    pub format: LogFormat,
+    pub rotation: LogRotation,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            path: "nettfiske.log".to_string(),
+            level: "info".to_string(),
+            timestamp: DEFAULT_TIMESTAMP.to_string(),
+            utc: false,
+            format: LogFormat::Plain,
+            rotation: LogRotation::default(),
+        }
+    }
+}
+
+impl LogConfig {
+    // Checks the level and rotation without setting up the logger
+    pub fn validate(&self) -> Result<()> {
+        self.level_filter()?;
+        self.rotation()?;
+        if !valid_time_format(&self.timestamp) {
+            bail!("Invalid timestamp format {}", self.timestamp);
+        }
+        Ok(())
+    }
+
+    fn level_filter(&self) -> Result<LevelFilter> {
+        LevelFilter::from_str(&self.level).chain_err(|| format!("Invalid log level {}", self.level))
+    }
+
+    fn rotation(&self) -> Result<Rotation> {
+        let rotation = &self.rotation;
+        match (rotation.max_size_mb, rotation.interval) {
+            (Some(_), Some(_)) => bail!("Log files rotate either on size or on time, not both"),
+            (Some(megabytes), None) => Ok(Rotation::Size(megabytes * 1024 * 1024)),
+            (None, Some(LogInterval::Hourly)) => Ok(Rotation::Hourly),
+            (None, Some(LogInterval::Daily)) => Ok(Rotation::Daily),
+            (None, None) => Ok(Rotation::Never),
+        }
+    }
+
+    fn format_timestamp(&self) -> String {
+        if self.utc {
+            format_time(&Utc::now(), &self.timestamp)
+        } else {
+            format_time(&Local::now(), &self.timestamp)
+        }
+    }
+}
+
+// The default format replaces an invalid one, formatting it would panic
+fn format_time<Tz: TimeZone>(now: &DateTime<Tz>, format: &str) -> String
+where
+    Tz::Offset: std::fmt::Display,
+{
+    if format.eq_ignore_ascii_case("rfc3339") {
+        now.to_rfc3339_opts(SecondsFormat::Millis, true)
+    } else if valid_time_format(format) {
+        now.format(format).to_string()
+    } else {
+        now.format(DEFAULT_TIMESTAMP).to_string()
+    }
+}
+
+fn valid_time_format(format: &str) -> bool {
+    format.eq_ignore_ascii_case("rfc3339")
+        || !StrftimeItems::new(format).any(|item| matches!(item, Item::Error))
+}
+
+pub fn setup_logger(config: &LogConfig) -> Result<()> {
+    let level = config.level_filter()?;
+    let rotation = config.rotation()?;
+
+    let format_config = config.clone();
+    let dispatch = fern::Dispatch::new()
+        .format(move |out, message, record| match format_config.format {
+            LogFormat::Plain => out.finish(format_args!(
+                "{} {}",
+                format_config.format_timestamp(),
+                message
+            )),
+            LogFormat::Json => out.finish(format_args!(
+                "{}",
+                json!({
+                    "timestamp": format_config.format_timestamp(),
+                    "level": record.level().to_string(),
+                    "target": record.target(),
+                    "message": message.to_string(),
+                })
+            )),
+        })
+        .level(level);
+
+    let dispatch = if rotation == Rotation::Never && !config.rotation.compress {
+        dispatch.chain(fern::log_file(&config.path)?)
+    } else {
+        let path = Path::new(&config.path);
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let prefix = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("nettfiske");
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("log");
+
+        let mut file = RotatingFile::new(
+            directory,
+            prefix,
+            extension,
+            rotation,
+            config.rotation.compress,
+        )?;
+        if let Some(keep) = config.rotation.keep {
+            file.set_retention(keep);
+        }
+
+        dispatch.chain(Box::new(file) as Box<dyn std::io::Write + Send>)
+    };
+
+    dispatch
+        .apply()
+        .chain_err(|| "A logger is already set up")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_invalid_timestamp_formats() {
+        let mut config = LogConfig::default();
+        assert!(config.validate().is_ok());
+
+        config.timestamp = "%Y-%m-%d %Q".to_string();
+        assert!(config.validate().is_err());
+
+        config.timestamp = "RFC3339".to_string();
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn falls_back_to_the_default_timestamp_format() {
+        let now = Utc.ymd(2026, 10, 17).and_hms(1, 2, 3);
+
+        assert_eq!(format_time(&now, "%Y %Q"), "[2026-10-17][01:02:03]");
+        assert_eq!(format_time(&now, "%d/%m"), "17/10");
+        assert_eq!(format_time(&now, "rfc3339"), "2026-10-17T01:02:03.000Z");
+    }
+}
@@ -1,126 +1,452 @@
 #[macro_use]
 extern crate log;
-#[macro_use]
-extern crate error_chain;
-#[macro_use]
-extern crate serde_derive;
-use serde_json;
-
-mod data;
-mod errors;
-mod nettfiske;
-mod websockets;
-
-use crate::errors::*;
-use crate::websockets::*;
-use std::{thread, time};
-use crate::nettfiske::{Nettfiske};
-use crate::data::CertString;
-use serde_json::{from_str};
+
+use nettfiske::archive::{Recorder, Rotation};
+use nettfiske::data::CertStreamConfig;
+use nettfiske::errors::*;
+use nettfiske::input::{open_input, read_domains, replay};
+use nettfiske::logging::setup_logger;
+use nettfiske::output::OutputFormat;
+use nettfiske::sink::{JsonlSink, SinkConfig, SinkKind, StdoutSink};
+use nettfiske::shutdown;
+use nettfiske::validate::validate;
+use nettfiske::websockets::*;
+use nettfiske::{CertStreamHandler, Config, Finding, Nettfiske, Severity, Sink};
 use console::{Emoji, style};
-use clap::{Arg, App};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use std::fs::File;
-use std::io::{BufReader, prelude::*};
+use std::io::{self, BufReader, prelude::*};
 
 static LOOKING_GLASS: Emoji<'_, '_> = Emoji("🔍  ", "");
 
-struct WebSocketHandler {
-    nettfiske: Nettfiske,
-    logging: bool,
-}
-
-impl EventHandler for WebSocketHandler {
-    fn on_connect(&mut self) {
-        if let Err(why) = self.nettfiske.setup_logger(self.logging) {
-            error!("Error setting UP log: {}", why)
-        }
-    }
-
-    fn on_data_event(&mut self, event: String) {
-        match from_str(&event) {
-            Ok(message) => {
-                let cert: CertString = message;
-                if cert.message_type.contains("certificate_update") {
-                    for domain in cert.data.leaf_cert.all_domains {
-                        self.nettfiske
-                            .analyse_domain(&domain, cert.data.chain.clone());
-                    }
-                }
-            }
-            Err(e) => {
-                error!("Received unknown message: {}", e);
-            }
-        }
-    }
-
-    fn on_error(&mut self, message: Error) {
-        display(format!("<<< Error<{:?}>", message));
-    }
-}
-
 fn main() {
     let matches = App::new("Nettfiske")
+        .setting(AppSettings::VersionlessSubcommands)
+        .after_help("Without a subcommand nettfiske watches the live certstream.")
         .args(&[
             Arg::with_name("input")
                 .help("the input file to use")
                 .index(1)
                 .required(true),
+            Arg::with_name("domains")
+                .help("Analyse the domains listed in a file (or - for stdin) instead of certstream, same as check --file")
+                .long("domains")
+                .value_name("FILE")
+                .takes_value(true),
             Arg::with_name("quiet")
                 .help("Be less verbose")
                 .short("q")
-                .long("quiet"),
+                .long("quiet")
+                .global(true),
             Arg::with_name("nolog")
                 .help("Don't output log file")
-                .long("nolog"),
+                .long("nolog")
+                .global(true),
+            Arg::with_name("suffix-list")
+                .help("Public suffix list to use instead of the bundled one")
+                .long("suffix-list")
+                .value_name("FILE")
+                .takes_value(true)
+                .global(true),
+            Arg::with_name("output")
+                .help("Output format of the findings [default: text]")
+                .long("output")
+                .value_name("FORMAT")
+                .takes_value(true)
+                .possible_values(&["text", "jsonl"])
+                .global(true),
+            Arg::with_name("explain")
+                .help("Show which signals contributed to the score")
+                .long("explain")
+                .global(true),
+            Arg::with_name("threshold")
+                .help("Score at which a severity tier starts, e.g. high=80")
+                .long("threshold")
+                .value_name("SEVERITY=SCORE")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .global(true),
+            Arg::with_name("min-severity")
+                .help("Only report findings of at least this severity")
+                .long("min-severity")
+                .value_name("SEVERITY")
+                .takes_value(true)
+                .possible_values(&["info", "low", "medium", "high", "critical"])
+                .global(true),
+        ])
+        .subcommands(vec![
+            SubCommand::with_name("watch")
+                .about("Analyse the certificates of the live certstream")
+                .args(&[
+                    Arg::with_name("record")
+                        .help("Archive the raw certstream messages as NDJSON files in this directory")
+                        .long("record")
+                        .value_name("DIR")
+                        .takes_value(true),
+                    Arg::with_name("rotate-size")
+                        .help("Start a new archive file after this many megabytes")
+                        .long("rotate-size")
+                        .value_name("MB")
+                        .takes_value(true)
+                        .requires("record"),
+                    Arg::with_name("rotate-hourly")
+                        .help("Start a new archive file every hour")
+                        .long("rotate-hourly")
+                        .requires("record")
+                        .conflicts_with("rotate-size"),
+                    Arg::with_name("compress")
+                        .help("Gzip the archive files")
+                        .long("compress")
+                        .requires("record"),
+                    Arg::with_name("url")
+                        .help("Certstream server to connect to, e.g. ws://localhost:4000/")
+                        .long("url")
+                        .value_name("URL")
+                        .takes_value(true),
+                    Arg::with_name("header")
+                        .help("Extra header for the websocket handshake, e.g. \"Authorization: Bearer ...\"")
+                        .long("header")
+                        .value_name("HEADER")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                    Arg::with_name("ca-cert")
+                        .help("PEM encoded CA certificate to trust for the certstream server")
+                        .long("ca-cert")
+                        .value_name("FILE")
+                        .takes_value(true),
+                    Arg::with_name("insecure")
+                        .help("Accept invalid TLS certificates from the certstream server")
+                        .long("insecure"),
+                ]),
+            SubCommand::with_name("check")
+                .about("Analyse the given domains and show their score breakdown")
+                .args(&[
+                    Arg::with_name("domain")
+                        .help("Domains to analyse")
+                        .index(1)
+                        .multiple(true)
+                        .required_unless("file"),
+                    Arg::with_name("file")
+                        .help("Also analyse the domains listed in a file (or - for stdin), reported like certstream findings")
+                        .long("file")
+                        .value_name("FILE")
+                        .takes_value(true),
+                ]),
+            SubCommand::with_name("replay")
+                .about("Analyse recorded certstream messages instead of the live stream")
+                .arg(
+                    Arg::with_name("file")
+                        .help("NDJSON recording, optionally gzipped, or - for stdin")
+                        .index(1)
+                        .required(true),
+                ),
+            SubCommand::with_name("validate-config")
+                .about("Check the input file for mistakes and exit"),
         ])
         .get_matches();
 
-    if let Some(file_name) = matches.value_of("input") {
-        let json_config = open_json_config(file_name).unwrap();
-        let config: data::Config = serde_json::from_str(&json_config).unwrap();
+    let file_name = matches.value_of("input").unwrap_or_default();
+    let (command, command_matches) = matches.subcommand();
+    // Global flags show up in the subcommand matches wherever they were given
+    let options = command_matches.unwrap_or(&matches);
+
+    let mut config = match load_config(file_name) {
+        Ok(config) => config,
+        Err(e) => exit_with(&e),
+    };
+
+    if command == "validate-config" {
+        let problems = validate(&config);
+        if problems.is_empty() {
+            println!(
+                "{} is valid: {} identities, {} sinks",
+                file_name,
+                config.identities.len(),
+                config.sinks.len()
+            );
+            return;
+        }
+        for problem in &problems {
+            println!("{} {}", style("warning:").yellow().bold(), problem);
+        }
+        std::process::exit(1);
+    }
+
+    for key in config.migrate_legacy_sinks() {
+        eprintln!(
+            "{} the top level {} setting is deprecated, add it to sinks",
+            style("warning:").yellow().bold(),
+            key
+        );
+    }
+
+    if let Err(e) = apply_options(&mut config, options) {
+        exit_with(&e);
+    }
 
-        let mut logging_enabled = !matches.is_present("nolog");
-        let is_present = !matches.is_present("quiet");
+    let logging_enabled = !options.is_present("nolog");
+    let output = options
+        .value_of("output")
+        .and_then(OutputFormat::from_name)
+        .unwrap_or_default();
 
-        loop {
-            if run(config.clone(), logging_enabled, is_present) {
-                break;
+    // Before the sinks are set up, so their errors are logged
+    if logging_enabled {
+        if let Err(why) = setup_logger(&config.log) {
+            eprintln!("Error setting up the log: {}", why)
+        }
+    }
+
+    let nettfiske = Nettfiske::new(config.clone());
+
+    let result = match (command, matches.value_of("domains")) {
+        ("check", None) => {
+            let domains: Vec<&str> = options.values_of("domain").into_iter().flatten().collect();
+            check_domains(&nettfiske, &config, &domains, output);
+            match options.value_of("file") {
+                Some(path) => report_domains(&nettfiske, path),
+                None => Ok(()),
             }
-            logging_enabled = false; // log already initialized
         }
+        ("", Some(path)) => report_domains(&nettfiske, path),
+        (_, Some(_)) => {
+            Err(format!("--domains can't be used with the {} subcommand", command).into())
+        }
+        ("replay", _) => replay_messages(nettfiske, options.value_of("file").unwrap_or("-")),
+        _ => {
+            // Keep stdout parseable when writing JSON
+            let banner = !options.is_present("quiet") && output == OutputFormat::Text;
+            watch(nettfiske, config.certstream, options, banner)
+        }
+    };
+
+    if let Err(e) = result {
+        exit_with(&e);
     }
 }
 
-fn run(config: data::Config, logging_enabled: bool, is_present: bool) -> bool {
-    let waiting_time = time::Duration::from_millis(5000);
+fn exit_with(error: &Error) -> ! {
+    let causes: Vec<String> = error.iter().map(|cause| cause.to_string()).collect();
+    eprintln!("{}", causes.join(": "));
+    std::process::exit(1);
+}
 
-    let mut web_socket: WebSockets = WebSockets::new();
+fn load_config(file_name: &str) -> Result<Config> {
+    let json_config =
+        open_json_config(file_name).chain_err(|| format!("Unable to read {}", file_name))?;
+    let config = serde_json::from_str(&json_config)
+        .chain_err(|| format!("Invalid configuration in {}", file_name))?;
 
-    web_socket.add_event_handler(WebSocketHandler {
-        nettfiske: Nettfiske::new(config),
-        logging: logging_enabled,
-    });
+    Ok(config)
+}
 
-    if let Ok(_answer) = web_socket.connect() {
-        if is_present {
-            println!(
-                "{} {} Fetching Certificates ...",
-                style("[Nettfiske]").bold().dim(),
-                LOOKING_GLASS
-            );
+// The global flags, shared by every subcommand
+fn apply_options(config: &mut Config, options: &ArgMatches<'_>) -> Result<()> {
+    if let Some(suffix_list) = options.value_of("suffix-list") {
+        config.public_suffix.path = Some(suffix_list.to_string());
+    }
+
+    for threshold in options.values_of("threshold").into_iter().flatten() {
+        let mut parts = threshold.splitn(2, '=');
+        let name = parts.next().unwrap_or_default().trim();
+        let severity = Severity::from_name(name)
+            .ok_or_else(|| format!("Unknown severity {} in --threshold", name))?;
+        let score: usize = parts
+            .next()
+            .and_then(|score| score.trim().parse().ok())
+            .ok_or("--threshold must be \"SEVERITY=SCORE\"")?;
+
+        match config
+            .scoring
+            .tiers
+            .iter_mut()
+            .find(|tier| tier.severity == severity)
+        {
+            Some(tier) => tier.threshold = score,
+            None => {
+                return Err(format!("There is no {} tier to set the threshold of", severity).into())
+            }
         }
     }
 
-    if let Err(_error) = web_socket.event_loop() {
-        thread::sleep(waiting_time); // sleep 5s
-        return false;
+    let min_severity = options
+        .value_of("min-severity")
+        .and_then(Severity::from_name)
+        .unwrap_or_default();
+    let output = options
+        .value_of("output")
+        .and_then(OutputFormat::from_name)
+        .unwrap_or_default();
+    let explain = options.is_present("explain");
+
+    // JSON findings are written to stdout even when no sink prints there
+    let prints = |sink: &SinkConfig| {
+        matches!(
+            sink.kind,
+            SinkKind::Stdout { .. } | SinkKind::Jsonl { path: None }
+        )
+    };
+    if output == OutputFormat::Jsonl && !config.sinks.iter().any(prints) {
+        config
+            .sinks
+            .push(SinkConfig::new(SinkKind::Stdout { explain: false }));
     }
 
-    true
+    for sink in &mut config.sinks {
+        sink.filter.min_severity = sink.filter.min_severity.max(min_severity);
+
+        match sink.kind {
+            SinkKind::Stdout {
+                explain: ref mut enabled,
+            }
+            | SinkKind::Log {
+                explain: ref mut enabled,
+            } => *enabled |= explain,
+            _ => {}
+        }
+        // JSON on stdout instead of the coloured lines
+        if output == OutputFormat::Jsonl {
+            if let SinkKind::Stdout { .. } = sink.kind {
+                sink.name = Some(sink.name().to_string());
+                sink.kind = SinkKind::Jsonl { path: None };
+            }
+        }
+    }
+
+    Ok(())
 }
 
-fn display(string: String) {
-    println!("{}", string);
+fn watch(
+    nettfiske: Nettfiske, mut certstream: CertStreamConfig, options: &ArgMatches<'_>, banner: bool,
+) -> Result<()> {
+    if let Some(url) = options.value_of("url") {
+        certstream.url = url.to_string();
+    }
+    for header in options.values_of("header").into_iter().flatten() {
+        let mut parts = header.splitn(2, ':');
+        let name = parts.next().unwrap_or_default().trim();
+        let value = parts
+            .next()
+            .ok_or("--header must be \"Name: value\"")?
+            .trim();
+        certstream
+            .headers
+            .insert(name.to_string(), value.to_string());
+    }
+    if let Some(ca_cert) = options.value_of("ca-cert") {
+        certstream.tls.ca_certificate = Some(ca_cert.to_string());
+    }
+    if options.is_present("insecure") {
+        certstream.tls.accept_invalid_certs = true;
+    }
+
+    let mut web_socket: WebSockets = WebSockets::with_config(certstream);
+
+    if let Some(directory) = options.value_of("record") {
+        let rotation = if let Some(size) = options.value_of("rotate-size") {
+            let megabytes: u64 = size
+                .parse()
+                .chain_err(|| "--rotate-size must be a number")?;
+            Rotation::Size(megabytes * 1024 * 1024)
+        } else if options.is_present("rotate-hourly") {
+            Rotation::Hourly
+        } else {
+            Rotation::Never
+        };
+
+        match Recorder::new(directory, rotation, options.is_present("compress")) {
+            Ok(recorder) => web_socket.set_recorder(recorder),
+            Err(e) => error!("Unable to record certstream messages: {}", e),
+        }
+    }
+
+    web_socket.add_event_handler(CertStreamHandler::new(nettfiske));
+
+    if banner {
+        println!(
+            "{} {} Fetching Certificates ...",
+            style("[Nettfiske]").bold().dim(),
+            LOOKING_GLASS
+        );
+    }
+
+    // Stop on SIGINT or SIGTERM, dropping the socket finishes the archive and
+    // the webhook deliveries
+    shutdown::install();
+    web_socket.run();
+    info!("Shutting down");
+
+    Ok(())
+}
+
+// Prints every domain with its breakdown, whether suspicious or not, bypassing
+// the sinks and deduplication
+fn check_domains(nettfiske: &Nettfiske, config: &Config, domains: &[&str], output: OutputFormat) {
+    let mut sink: Box<dyn Sink> = match output {
+        OutputFormat::Text => Box::new(StdoutSink::new(config.scoring.tiers.clone(), true)),
+        OutputFormat::Jsonl => Box::new(JsonlSink::new(Box::new(io::stdout()))),
+    };
+
+    let mut check = |domain: &str| {
+        let finding = Finding::from(nettfiske.analyse_name(domain));
+        if finding.is_suspicious() || output == OutputFormat::Jsonl {
+            sink.send(&finding);
+        } else {
+            print_not_suspicious(&finding);
+        }
+    };
+
+    for domain in domains {
+        check(domain);
+    }
+}
+
+// Reports the suspicious domains of a list through the sinks, like certstream
+// findings, so long lists can be triaged with the usual filters
+fn report_domains(nettfiske: &Nettfiske, path: &str) -> Result<()> {
+    for domain in read_domains(open_input(path)?) {
+        let mut finding = Finding::from(nettfiske.analyse_name(&domain?));
+        if finding.is_suspicious() && nettfiske.deduplicate(&mut finding) {
+            nettfiske.report(&finding);
+        }
+    }
+
+    info!("{}", nettfiske.metrics());
+
+    Ok(())
+}
+
+fn print_not_suspicious(finding: &Finding) {
+    let verdict = match finding.primary() {
+        Some(verdict) => verdict,
+        None => return,
+    };
+
+    if verdict.allowlisted {
+        println!("Allowlisted {}", verdict.display_domain());
+        return;
+    }
+
+    println!(
+        "Not suspicious {} (score {})",
+        verdict.display_domain(),
+        finding.score
+    );
+    if !verdict.signals.is_empty() {
+        println!("    {}", style(finding.breakdown()).dim());
+    }
+}
+
+fn replay_messages(nettfiske: Nettfiske, path: &str) -> Result<()> {
+    let mut handler = CertStreamHandler::new(nettfiske);
+
+    let messages = replay(open_input(path)?, &mut handler)?;
+    info!("Replayed {} messages from {}", messages, path);
+    info!("{}", handler.nettfiske().metrics());
+
+    Ok(())
 }
 
 fn open_json_config(file_name: &str) -> Result<String> {
@@ -0,0 +1,68 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Counters kept for the lifetime of the detector
+#[derive(Debug, Default)]
+pub struct Metrics {
+    domains_analysed: AtomicUsize,
+    domains_allowlisted: AtomicUsize,
+    identities_allowlisted: AtomicUsize,
+    findings: AtomicUsize,
+    duplicates: AtomicUsize,
+}
+
+impl Metrics {
+    pub fn domain_analysed(&self) {
+        self.domains_analysed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn domain_allowlisted(&self) {
+        self.domains_allowlisted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn identity_allowlisted(&self) {
+        self.identities_allowlisted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn finding(&self) {
+        self.findings.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn duplicate(&self) {
+        self.duplicates.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn domains_analysed(&self) -> usize {
+        self.domains_analysed.load(Ordering::Relaxed)
+    }
+
+    pub fn domains_allowlisted(&self) -> usize {
+        self.domains_allowlisted.load(Ordering::Relaxed)
+    }
+
+    pub fn identities_allowlisted(&self) -> usize {
+        self.identities_allowlisted.load(Ordering::Relaxed)
+    }
+
+    pub fn findings(&self) -> usize {
+        self.findings.load(Ordering::Relaxed)
+    }
+
+    pub fn duplicates(&self) -> usize {
+        self.duplicates.load(Ordering::Relaxed)
+    }
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} domains analysed, {} allowlisted, {} identity matches allowlisted, {} findings, {} duplicates",
+            self.domains_analysed(),
+            self.domains_allowlisted(),
+            self.identities_allowlisted(),
+            self.findings(),
+            self.duplicates()
+        )
+    }
+}
@@ -0,0 +1,20 @@
+use crate::errors::*;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+// Tries each resolved address in turn, waiting at most the timeout for each
+pub fn connect_timeout<A: ToSocketAddrs>(address: A, timeout: Duration) -> Result<TcpStream> {
+    let mut last_error = None;
+
+    for address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    match last_error {
+        Some(e) => Err(e.into()),
+        None => bail!("No address found"),
+    }
+}
@@ -1,62 +1,198 @@
-use fern;
-use chrono;
-
-use crate::data::{Certificate, Config, ChainObjects};
-use log::{LevelFilter};
-use publicsuffix::List;
-use console::{style};
+use crate::certificate::CertificateSubjects;
+use crate::data::{Config, ChainObjects, Data, WebsiteIdentity};
+use crate::dedup::DedupCache;
+use crate::errors::*;
+use crate::logging::setup_logger;
+use crate::metrics::Metrics;
+use crate::sink::{Dispatcher, Sink, SinkFilter};
+use crate::verdict::{CertificateDetails, Finding, SignalKind, Verdict};
+use std::collections::BTreeSet;
+use crate::suffix::{load_list, spawn_refresh, SharedList};
+use std::sync::{Arc, Mutex, RwLock};
 use strsim::{damerau_levenshtein};
 use idna::punycode::{decode};
 use unicode_skeleton::{UnicodeSkeleton};
 
 pub struct Nettfiske {
-    list: List,
+    list: SharedList,
     config: Config,
+    metrics: Metrics,
+    dedup: Mutex<DedupCache>,
+    sinks: Mutex<Dispatcher>,
 }
 
 impl Nettfiske {
-    pub fn new(config: Config) -> Self {
+    pub fn new(mut config: Config) -> Self {
+        for key in config.migrate_legacy_sinks() {
+            warn!(
+                "The top level {} setting is deprecated, add it to sinks",
+                key
+            );
+        }
+
+        let list = Arc::new(RwLock::new(load_list(&config.public_suffix)));
+        spawn_refresh(&list, &config.public_suffix);
+
         Nettfiske {
-            list: List::fetch().unwrap(),
+            list,
+            dedup: Mutex::new(DedupCache::new(&config.dedup)),
+            sinks: Mutex::new(Dispatcher::from_config(&config.sinks, &config.scoring)),
             config,
+            metrics: Metrics::default(),
         }
     }
 
-    pub fn setup_logger(&self, enable: bool) -> Result<(), fern::InitError> {
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    // Sends the reported findings to a further sink, next to the configured ones.
+    // The name can be used in the severity tier sinks lists.
+    pub fn add_sink(&mut self, name: &str, filter: SinkFilter, sink: Box<dyn Sink>) {
+        self.sinks.lock().unwrap().register(name, filter, sink);
+    }
+
+    pub fn setup_logger(&self, enable: bool) -> Result<()> {
         if !enable {
             return Ok(());
         }
 
-        fern::Dispatch::new()
-            .format(|out, message, _record| {
-                out.finish(format_args!(
-                    "{} {}",
-                    chrono::Local::now().format("[%Y-%m-%d][%H:%M:%S]"),
-                    message
-                ))
+        setup_logger(&self.config.log)
+    }
+
+    pub fn analyse_domain(&self, original_domain: &str, chain: Vec<ChainObjects>) -> Verdict {
+        let certificate = self.certificate_info(chain);
+        self.analyse(original_domain, Some(&certificate))
+    }
+
+    // Analyse all the names of a certificate and score it as a whole, phishing
+    // certificates often bundle several lookalikes of the same or different brands.
+    pub fn analyse_certificate(&self, data: &Data) -> Finding {
+        let certificate = CertificateSubjects {
+            subject: Some(data.leaf_cert.subject.clone()),
+            issuer: match data.leaf_cert.issuer {
+                Some(ref issuer) => Some(issuer.clone()),
+                None => self.certificate_info(data.chain.clone()).issuer,
+            },
+        };
+        let verdicts = data
+            .leaf_cert
+            .all_domains
+            .iter()
+            .map(|domain| self.analyse(domain, Some(&certificate)))
+            .collect();
+
+        let mut finding = Finding::new(verdicts);
+        finding.certificate = Some(CertificateDetails::from(data));
+        let scoring = &self.config.scoring;
+
+        // Names containing a brand keyword
+        let branded_names = finding
+            .verdicts
+            .iter()
+            .filter(|verdict| {
+                verdict
+                    .signals
+                    .iter()
+                    .any(|signal| signal.kind == SignalKind::Keyword)
             })
-            .level(LevelFilter::Info)
-            .chain(fern::log_file("nettfiske.log")?)
-            .apply()?;
-        Ok(())
+            .count();
+
+        let identities: BTreeSet<&str> = finding
+            .verdicts
+            .iter()
+            .filter_map(|verdict| verdict.identity.as_deref())
+            .collect();
+        let brand_count = identities.len();
+        let mixed_brands = identities.into_iter().collect::<Vec<&str>>().join("+");
+
+        if branded_names > 1 {
+            let identity = finding.identity.clone();
+            finding.add_signal(
+                SignalKind::BrandedNames,
+                &format!("{} names", branded_names),
+                identity.as_deref(),
+                (branded_names - 1) * scoring.branded_names_points,
+            );
+        }
+        if brand_count > 1 {
+            finding.add_signal(
+                SignalKind::MixedBrands,
+                &mixed_brands,
+                None,
+                scoring.mixed_brands_points,
+            );
+        }
+
+        if !finding.verdicts.is_empty() {
+            for rule in &scoring.issuer_rules {
+                if rule.applies(&data.leaf_cert, certificate.issuer.as_ref()) {
+                    finding.add_signal(SignalKind::IssuerRule, &rule.name, None, rule.points);
+                }
+            }
+        }
+
+        finding.severity = scoring.severity(finding.score, finding.punycode_detected());
+        finding
     }
 
-    pub fn analyse_domain(&self, original_domain: &str, chain: Vec<ChainObjects>) {
-        let mut punycode_detected = false;
-        let mut score = 0;
+    // Analyse a bare domain name, e.g. from a list, without any certificate
+    pub fn analyse_name(&self, original_domain: &str) -> Verdict {
+        self.analyse(original_domain, None)
+    }
 
-        let original_domain_str = original_domain.replace("*.", "");
+    fn analyse(&self, original_domain: &str, certificate: Option<&CertificateSubjects>) -> Verdict {
+        // Wildcard certificate, e.g. *.example.com covers any subdomain of example.com
+        let (wildcard, original_domain_str) = match original_domain.strip_prefix("*.") {
+            Some(covered) => (true, covered.to_string()),
+            None => (false, original_domain.to_string()),
+        };
 
         let domain = self.punycode(original_domain_str.to_string());
 
         // It means that found punycode
-        if original_domain_str != domain {
-            punycode_detected = true;
+        let punycode_detected = original_domain_str != domain;
+
+        let mut verdict = Verdict::new(original_domain, &domain, punycode_detected);
+        verdict.wildcard = wildcard;
+        let mut lookalike_identity = None;
+        let mut identity_score = 0;
+
+        self.metrics.domain_analysed();
+
+        // Allowlists are matched on the ASCII form, the decoded skeleton of a
+        // homoglyph would otherwise match the domain it imitates.
+        let list = self.list.read().unwrap();
+
+        let ascii_root = match list.parse_domain(&original_domain_str) {
+            Ok(ascii_domain) => ascii_domain.root().map(|root| root.to_string()),
+            Err(_) => None,
+        };
+
+        if self
+            .config
+            .allowlist
+            .contains(&original_domain_str, ascii_root.as_deref())
+        {
+            self.metrics.domain_allowlisted();
+            verdict.allowlisted = true;
+            return verdict;
         }
 
-        let certificate = self.certificate_info(chain);
+        // Legitimate certificate of one of the identities
+        if let Some(certificate) = certificate {
+            if self.config.identities.iter().any(|identity| {
+                identity
+                    .profiles()
+                    .any(|profile| profile.matches(certificate))
+            }) {
+                self.metrics.identity_allowlisted();
+                verdict.allowlisted = true;
+                return verdict;
+            }
+        }
 
-        if let Ok(domain_obj) = self.list.parse_domain(&domain) {
+        if let Ok(domain_obj) = list.parse_domain(&domain) {
             if let Some(registrable) = domain_obj.root() {
                 // Registrable domain
                 let domain_name: Vec<&str> = registrable.split('.').collect();
@@ -67,59 +203,172 @@ impl Nettfiske {
 
                 let sub_domain_name: Vec<&str> = sub_domain.split('.').collect();
 
-                for identities in &self.config.identities {
-                    let key = identities.common_name.as_str();
+                for identity in &self.config.identities {
+                    // Legitimate domain of the identity
+                    if identity.owns(&original_domain_str, ascii_root.as_deref()) {
+                        self.metrics.identity_allowlisted();
+                        continue;
+                    }
 
-                    if identities.certificate.issued_to == certificate.issued_to
-                        && identities.certificate.issued_by == certificate.issued_by
-                    {
-                        return;
+                    let score_before = verdict.score;
+                    let lookalike = self.score_identity(
+                        &mut verdict,
+                        identity,
+                        domain_name[0],
+                        &sub_domain_name,
+                    );
+                    if lookalike && lookalike_identity.is_none() {
+                        lookalike_identity = Some(identity.common_name.as_str());
                     }
 
-                    // Check Registration domain
-                    score += self.domain_keywords(domain_name[0], key) * 4;
-                    score +=
-                        self.calc_string_edit_distance(domain_name[0], key, 6, punycode_detected);
-
-                    // Check subdomain
-                    for name in &sub_domain_name {
-                        score += self.domain_keywords(name, key) * 5;
-                        if !name.contains("mail") && !name.contains("cloud") {
-                            score +=
-                                self.calc_string_edit_distance(name, key, 4, punycode_detected);
-                        }
+                    let partial = verdict.score - score_before;
+                    if partial > identity_score {
+                        identity_score = partial;
+                        verdict.identity = Some(identity.common_name.clone());
                     }
                 }
 
                 // Check for tldl on subdomain
-                score += self.search_tldl_on_subdomain(&sub_domain_name);
+                if let Some((name, points)) = self.search_tldl_on_subdomain(&sub_domain_name) {
+                    verdict.add_signal(SignalKind::TldOnSubdomain, name, None, points);
+                }
+
+                // A wildcard under a lookalike registrable domain covers any number
+                // of phishing subdomains
+                if wildcard && lookalike_identity.is_some() {
+                    verdict.add_signal(
+                        SignalKind::WildcardLookalike,
+                        registrable,
+                        lookalike_identity,
+                        self.config.scoring.wildcard_lookalike_points,
+                    );
+                }
             }
         }
 
-        score += self.deeply_nested(&domain);
+        let nested = self.deeply_nested(&domain);
+        verdict.add_signal(SignalKind::DeeplyNested, &domain, None, nested);
 
-        self.report(score, &original_domain, &domain, punycode_detected);
+        verdict.severity = self
+            .config
+            .scoring
+            .severity(verdict.score, punycode_detected);
+        if verdict.is_suspicious() {
+            self.metrics.finding();
+        }
+        verdict
+    }
+
+    // Returns whether the registrable domain itself imitates the identity
+    fn score_identity(
+        &self, verdict: &mut Verdict, identity: &WebsiteIdentity, domain_name: &str,
+        sub_domain_name: &[&str],
+    ) -> bool {
+        let scoring = &self.config.scoring;
+
+        // Check Registration domain
+        let lookalike = self.score_label(
+            verdict,
+            identity,
+            domain_name,
+            scoring.registrable_keyword_weight,
+            Some(scoring.registrable_edit_distance_weight),
+        );
+
+        // Check subdomain
+        for name in sub_domain_name {
+            let edit_distance_weight = if identity.ignores(name) {
+                None
+            } else {
+                Some(scoring.subdomain_edit_distance_weight)
+            };
+            self.score_label(
+                verdict,
+                identity,
+                name,
+                scoring.subdomain_keyword_weight,
+                edit_distance_weight,
+            );
+        }
+
+        lookalike
+    }
+
+    // Scores a label once per identity: for the first keyword it contains, and for
+    // the keyword it is closest to by edit distance. A label that is one of the
+    // keywords is not compared by edit distance, e.g. "paypal" against "paypa1",
+    // unless the domain has punycode where it means a homoglyph. Returns whether
+    // the label scored.
+    fn score_label(
+        &self, verdict: &mut Verdict, identity: &WebsiteIdentity, label: &str,
+        keyword_weight: usize, edit_distance_weight: Option<usize>,
+    ) -> bool {
+        let common_name = Some(identity.common_name.as_str());
+        let keywords: Vec<&str> = identity.keywords().filter(|key| !key.is_empty()).collect();
+
+        let mut scored = false;
+
+        if let Some(key) = keywords.iter().find(|key| label.contains(*key)) {
+            let points = self.domain_keywords(label, key) * keyword_weight;
+            verdict.add_keyword_signal(
+                SignalKind::Keyword,
+                label,
+                common_name,
+                key,
+                identity.weighted(points),
+            );
+            scored = points > 0;
+        }
+
+        let weight = match edit_distance_weight {
+            Some(weight) if verdict.punycode_detected || !keywords.contains(&label) => weight,
+            _ => return scored,
+        };
+        let mut closest: Option<(&str, usize)> = None;
+        for key in &keywords {
+            let points =
+                self.calc_string_edit_distance(label, key, weight, verdict.punycode_detected);
+            if points > closest.map_or(0, |(_, best)| best) {
+                closest = Some((key, points));
+            }
+        }
+
+        match closest {
+            Some((key, points)) => {
+                verdict.add_keyword_signal(
+                    SignalKind::EditDistance,
+                    label,
+                    common_name,
+                    key,
+                    identity.weighted(points),
+                );
+                true
+            }
+            None => scored,
+        }
     }
 
     #[allow(clippy::never_loop)]
-    fn search_tldl_on_subdomain(&self, sub_domain: &[&str]) -> usize {
+    fn search_tldl_on_subdomain<'a>(&self, sub_domain: &[&'a str]) -> Option<(&'a str, usize)> {
         let tldl: Vec<&str> = vec!["com", "net", "-net", "-com", "net-", "com-", "com/", "net/"];
+        let weight = self.config.scoring.tld_on_subdomain_weight;
         for key in &tldl {
             for name in sub_domain {
                 if *key == "com" || *key == "net" {
-                    return self.domain_keywords_exact_match(&name, key) * 4;
+                    return Some((name, self.domain_keywords_exact_match(name, key) * weight));
                 } else {
-                    return self.domain_keywords(&name, key) * 4;
+                    return Some((name, self.domain_keywords(name, key) * weight));
                 }
             }
         }
-        0
+        None
     }
 
     fn deeply_nested(&self, domain: &str) -> usize {
+        let scoring = &self.config.scoring;
         let v: Vec<&str> = domain.split('.').collect();
-        if v.len() >= 3 {
-            v.len() * 3
+        if v.len() >= scoring.nested_min_labels {
+            v.len() * scoring.nested_label_points
         } else {
             0
         }
@@ -127,14 +376,14 @@ impl Nettfiske {
 
     fn domain_keywords(&self, name: &str, key: &str) -> usize {
         if name.contains(key) {
-            return 10;
+            return self.config.scoring.keyword_points;
         }
         0
     }
 
     fn domain_keywords_exact_match(&self, name: &str, key: &str) -> usize {
         if name.eq_ignore_ascii_case(key) {
-            return 10;
+            return self.config.scoring.keyword_points;
         }
         0
     }
@@ -147,9 +396,9 @@ impl Nettfiske {
         let distance = damerau_levenshtein(name, key);
 
         if (distance == 1 || distance == 0) && punycode_detected {
-            return 15 * weight;
+            return self.config.scoring.homoglyph_edit_distance_points * weight;
         } else if distance == 1 {
-            return 8 * weight;
+            return self.config.scoring.edit_distance_points * weight;
         }
         0
     }
@@ -162,9 +411,14 @@ impl Nettfiske {
         for word in &words_list {
             if word.starts_with("xn--") {
                 let pu = word.replace("xn--", "");
-                let decoded = decode(&pu).unwrap().into_iter().collect::<String>();
-                let skeleton = decoded.skeleton_chars().collect::<String>();
-                result.push(skeleton.clone());
+                // Malformed labels are kept as they are
+                match decode(&pu) {
+                    Some(decoded) => {
+                        let decoded = decoded.into_iter().collect::<String>();
+                        result.push(decoded.skeleton_chars().collect::<String>());
+                    }
+                    None => result.push((*word).to_string()),
+                }
             } else {
                 result.push((*word).to_string());
             }
@@ -173,48 +427,431 @@ impl Nettfiske {
         result.join(".")
     }
 
-    fn report(&self, score: usize, domain_original: &str, domain: &str, punycode_detected: bool) {
-        if score >= 90 && punycode_detected {
-            println!(
-                "Homoglyph detected {} (Punycode: {})",
-                style(domain).red().on_black().bold(),
-                domain_original
-            );
-        } else if score >= 90 {
-            println!("Suspicious {} (score {})", style(domain).red(), score);
-        } else if score >= 70 {
-            println!("Suspicious {} (score {})", style(domain).yellow(), score);
-        } else if score >= 56 {
-            println!(
-                "Suspicious {} (score {})",
-                style(domain_original).magenta(),
-                score
-            );
-        }
+    // Drops the names already reported within the dedup window and returns false
+    // when the finding is no longer worth reporting, otherwise records on each name
+    // how often it was seen since the last report. Only the names that make the
+    // finding suspicious are remembered, or the primary one when the certificate
+    // level signals do, so a harmless name doesn't hide a later suspicious one.
+    pub fn deduplicate(&self, finding: &mut Finding) -> bool {
+        let mut dedup = self.dedup.lock().unwrap();
+        let any_suspicious = finding.verdicts.iter().any(|v| v.is_suspicious());
+        let names = finding.verdicts.len();
+        let mut remembered = 0;
+
+        let mut index = 0;
+        finding.verdicts.retain_mut(|verdict| {
+            let primary = index == 0;
+            index += 1;
+            if !verdict.is_suspicious() && (any_suspicious || !primary) {
+                return true;
+            }
 
-        if score >= 56 {
-            if punycode_detected {
-                info!("{} - (Punycode: {})", domain, domain_original);
-            } else {
-                info!("{}", domain);
+            match dedup.check(&verdict.original_domain) {
+                Some(duplicates) => {
+                    verdict.duplicates = duplicates;
+                    remembered += 1;
+                    true
+                }
+                None => false,
             }
+        });
+
+        if finding.verdicts.len() < names {
+            self.rescore(finding);
+        }
+
+        if remembered == 0 || !finding.is_suspicious() {
+            self.metrics.duplicate();
+            return false;
+        }
+        true
+    }
+
+    // Score, severity and identity of the most suspicious name left, plus the
+    // certificate level signals
+    fn rescore(&self, finding: &mut Finding) {
+        let (score, identity) = match finding.primary() {
+            Some(top) => (top.score, top.identity.clone()),
+            None => (0, None),
+        };
+
+        finding.score = score + finding.signals.iter().map(|s| s.points).sum::<usize>();
+        finding.identity = identity;
+        finding.severity = self
+            .config
+            .scoring
+            .severity(finding.score, finding.punycode_detected());
+    }
+
+    pub fn report(&self, finding: &Finding) {
+        if let Some(tier) = self.config.scoring.tier_for(finding.severity) {
+            self.sinks.lock().unwrap().dispatch(finding, tier);
         }
     }
 
-    pub fn certificate_info(&self, chain: Vec<ChainObjects>) -> Certificate {
-        if chain.len() > 1 {
-            let sub_to = chain[0].subject.clone();
-            let sub_by = chain[1].subject.clone();
-            Certificate {
-                issued_to: sub_to.organization.unwrap_or_else(|| "".to_string()),
-                issued_by: sub_by.organization.unwrap_or_else(|| "".to_string()),
+    // Without the leaf certificate only the issuer is known, the first certificate
+    // of the chain.
+    pub fn certificate_info(&self, chain: Vec<ChainObjects>) -> CertificateSubjects {
+        CertificateSubjects {
+            subject: None,
+            issuer: chain.into_iter().next().map(|issuer| issuer.subject),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::CertString;
+    use crate::verdict::Severity;
+
+    fn nettfiske() -> Nettfiske {
+        let mut config: Config = serde_json::from_str(include_str!("../sample.json")).unwrap();
+        config.sinks = Vec::new();
+        Nettfiske::new(config)
+    }
+
+    #[test]
+    fn rescores_a_finding_without_its_duplicate_names() {
+        let nettfiske = nettfiske();
+        let mut first = Finding::from(Verdict::scored("paypal-login.com", 95, Severity::High));
+        assert!(nettfiske.deduplicate(&mut first));
+
+        let mut second = Finding::new(vec![
+            Verdict::scored("paypal-login.com", 95, Severity::High),
+            Verdict::scored("paypal-verify.net", 72, Severity::Medium),
+        ]);
+        assert!(nettfiske.deduplicate(&mut second));
+
+        assert_eq!(second.verdicts.len(), 1);
+        assert_eq!(second.verdicts[0].original_domain, "paypal-verify.net");
+        assert_eq!(second.score, 72);
+        assert_eq!(second.severity, Severity::Medium);
+
+        let mut third = Finding::from(Verdict::scored("paypal-login.com", 95, Severity::High));
+        assert!(!nettfiske.deduplicate(&mut third));
+    }
+
+    #[test]
+    fn does_not_remember_harmless_names() {
+        let nettfiske = nettfiske();
+        let mut first = Finding::new(vec![
+            Verdict::scored("paypal-login.com", 95, Severity::High),
+            Verdict::scored("paypal-shop.com", 40, Severity::None),
+        ]);
+        assert!(nettfiske.deduplicate(&mut first));
+        assert_eq!(first.verdicts.len(), 2);
+
+        let mut second = Finding::from(Verdict::scored("paypal-shop.com", 60, Severity::Low));
+        assert!(nettfiske.deduplicate(&mut second));
+        assert_eq!(second.score, 60);
+    }
+
+    #[test]
+    fn keeps_invalid_punycode_labels() {
+        let verdict = nettfiske().analyse_name("xn--zz.com");
+
+        assert_eq!(verdict.domain, "xn--zz.com");
+        assert!(!verdict.punycode_detected);
+    }
+
+    #[test]
+    fn decodes_punycode_labels() {
+        let verdict = nettfiske().analyse_name("xn--pypal-4ve.com");
+
+        assert!(verdict.punycode_detected);
+        assert_eq!(verdict.identity.as_deref(), Some("paypal"));
+        assert_eq!(verdict.score, 130);
+        assert_eq!(verdict.severity, Severity::Critical);
+    }
+
+    #[test]
+    fn scores_a_label_once_per_identity() {
+        let verdict = nettfiske().analyse_name("paypal.x.com");
+
+        assert_eq!(verdict.score, 59);
+        assert_eq!(
+            verdict.breakdown(),
+            "keyword paypal ~ paypal (+50), deeply_nested paypal.x.com (+9)"
+        );
+    }
+
+    #[test]
+    fn scores_keyword_and_edit_distance_lookalikes() {
+        let nettfiske = nettfiske();
+
+        let registrable = nettfiske.analyse_name("paypals.com");
+        assert_eq!(registrable.score, 88);
+        assert_eq!(registrable.severity, Severity::Medium);
+        assert_eq!(
+            registrable.breakdown(),
+            "keyword paypals ~ paypal (+40), edit_distance paypals ~ paypal (+48)"
+        );
+
+        let subdomain = nettfiske.analyse_name("paypals.x.com");
+        assert_eq!(subdomain.score, 91);
+        assert_eq!(subdomain.severity, Severity::High);
+    }
+
+    #[test]
+    fn names_the_matching_keyword() {
+        let verdict = nettfiske().analyse_name("secure-paypa1.com");
+
+        assert_eq!(verdict.score, 40);
+        assert_eq!(verdict.signals.len(), 1);
+        assert_eq!(verdict.signals[0].keyword.as_deref(), Some("paypa1"));
+        assert_eq!(
+            verdict.breakdown(),
+            "keyword secure-paypa1 ~ paypal via paypa1 (+40)"
+        );
+    }
+
+    // The identities of the README examples
+    fn example() -> Nettfiske {
+        let mut config: Config =
+            serde_json::from_str(include_str!("../tests/fixtures/example.json")).unwrap();
+        config.sinks = Vec::new();
+        Nettfiske::new(config)
+    }
+
+    #[test]
+    fn scores_the_readme_examples() {
+        let nettfiske = example();
+
+        let examples = [
+            ("xn--youtue-tg7b.com", 90, Severity::Critical),
+            ("xn--hatsapp-h41c.com", 130, Severity::Critical),
+            ("xn--twiter-507b.com", 90, Severity::Critical),
+            ("paypal.com-secure.warn-allmail.com", 62, Severity::Low),
+            (
+                "xn--applid-lva.xn--ppl-8ka7c.com.iosets.com",
+                65,
+                Severity::Low,
+            ),
+            ("facebook.com-verified-id939819835.com", 59, Severity::Low),
+            ("appleid.apple.com.invoice-qwery.gq", 115, Severity::High),
+            (
+                "instagramaccountverifica.altervista.org",
+                49,
+                Severity::None,
+            ),
+        ];
+        for &(domain, score, severity) in &examples {
+            let verdict = nettfiske.analyse_name(domain);
+            assert_eq!(
+                (verdict.score, verdict.severity),
+                (score, severity),
+                "{}",
+                domain
+            );
+        }
+    }
+
+    #[test]
+    fn scores_names_without_certificate_data() {
+        let nettfiske = nettfiske();
+        let certificate = nettfiske.certificate_info(Vec::new());
+        assert!(certificate.subject.is_none());
+        assert!(certificate.issuer.is_none());
+
+        let verdict = nettfiske.analyse_domain("paypal-login.com", Vec::new());
+
+        assert!(!verdict.allowlisted);
+        assert_eq!(verdict.identity.as_deref(), Some("paypal"));
+    }
+
+    fn allowlisting() -> Nettfiske {
+        let config = r#"{
+            "identities": [
+                {
+                    "common_name": "paypal",
+                    "domains": ["paypal.com"],
+                    "allowlist": { "domains": ["paypal-facebook.com"] }
+                },
+                { "common_name": "facebook" }
+            ],
+            "allowlist": { "patterns": ["*.paypal-partner.net"] },
+            "sinks": []
+        }"#;
+        Nettfiske::new(serde_json::from_str(config).unwrap())
+    }
+
+    #[test]
+    fn skips_globally_allowlisted_domains() {
+        let nettfiske = allowlisting();
+
+        let verdict = nettfiske.analyse_name("facebook.paypal-partner.net");
+
+        assert!(verdict.allowlisted);
+        assert_eq!(verdict.score, 0);
+        assert_eq!(nettfiske.metrics().domains_allowlisted(), 1);
+        assert_eq!(nettfiske.metrics().identities_allowlisted(), 0);
+    }
+
+    #[test]
+    fn identity_allowlists_only_cover_their_identity() {
+        let nettfiske = allowlisting();
+
+        let verdict = nettfiske.analyse_name("paypal-facebook.com");
+
+        assert!(!verdict.allowlisted);
+        assert_eq!(verdict.identity.as_deref(), Some("facebook"));
+        assert_eq!(nettfiske.metrics().identities_allowlisted(), 1);
+    }
+
+    #[test]
+    fn allowlists_do_not_cover_homoglyphs() {
+        let nettfiske = allowlisting();
+
+        let owned = nettfiske.analyse_name("www.paypal.com");
+        assert_eq!(owned.identity, None);
+        assert_eq!(owned.severity, Severity::None);
+
+        let verdict = nettfiske.analyse_name("xn--pypal-4ve.com");
+        assert!(!verdict.allowlisted);
+        assert_eq!(verdict.severity, Severity::Critical);
+    }
+
+    #[test]
+    fn applies_issuer_rules_to_the_chain_issuer() {
+        let mut config: Config = serde_json::from_str(include_str!("../sample.json")).unwrap();
+        config.sinks = Vec::new();
+        config.scoring.issuer_rules = serde_json::from_str(
+            r#"[{ "name": "free_dv", "points": 15, "issuers": ["Let's Encrypt"] }]"#,
+        )
+        .unwrap();
+        let frame = include_str!("../tests/fixtures/certificate_update.json");
+        let message: CertString = serde_json::from_str(frame).unwrap();
+        assert!(message.data.leaf_cert.issuer.is_none());
+
+        let finding = Nettfiske::new(config).analyse_certificate(&message.data);
+
+        assert!(finding
+            .signals
+            .iter()
+            .any(|signal| signal.kind == SignalKind::IssuerRule && signal.label == "free_dv"));
+    }
+
+    #[test]
+    fn reports_to_added_sinks_by_tier() {
+        struct Recording(Arc<Mutex<Vec<String>>>);
+
+        impl Sink for Recording {
+            fn send(&mut self, finding: &Finding) {
+                let domain = finding.primary().unwrap().original_domain.clone();
+                self.0.lock().unwrap().push(domain);
             }
-        } else {
-            let sub_to = chain[0].subject.clone();
-            Certificate {
-                issued_to: sub_to.organization.unwrap_or_else(|| "".to_string()),
-                issued_by: "".to_string(),
+        }
+
+        let mut config: Config = serde_json::from_str(include_str!("../sample.json")).unwrap();
+        config.sinks = Vec::new();
+        for tier in &mut config.scoring.tiers {
+            if tier.severity == Severity::Critical {
+                tier.sinks = Some(vec!["homoglyphs".to_string()]);
             }
         }
+        let mut nettfiske = Nettfiske::new(config);
+        let everything = Arc::new(Mutex::new(Vec::new()));
+        let homoglyphs = Arc::new(Mutex::new(Vec::new()));
+        nettfiske.add_sink(
+            "everything",
+            SinkFilter::default(),
+            Box::new(Recording(everything.clone())),
+        );
+        nettfiske.add_sink(
+            "homoglyphs",
+            SinkFilter::default(),
+            Box::new(Recording(homoglyphs.clone())),
+        );
+
+        for domain in &["paypals.com", "xn--pypal-4ve.com", "google.com"] {
+            let finding = Finding::from(nettfiske.analyse_name(domain));
+            nettfiske.report(&finding);
+        }
+
+        assert_eq!(*everything.lock().unwrap(), ["paypals.com"]);
+        assert_eq!(
+            *homoglyphs.lock().unwrap(),
+            ["paypals.com", "xn--pypal-4ve.com"]
+        );
+    }
+
+    #[test]
+    fn scores_wildcards_under_lookalike_domains() {
+        let verdict = example().analyse_name("*.paypa1.com");
+
+        assert!(verdict.wildcard);
+        assert_eq!(verdict.domain, "paypa1.com");
+        assert_eq!(verdict.display_domain(), "*.paypa1.com");
+        assert_eq!(verdict.score, 68);
+        assert_eq!(
+            verdict.breakdown(),
+            "edit_distance paypa1 ~ paypal (+48), wildcard_lookalike paypa1.com ~ paypal (+20)"
+        );
+    }
+
+    #[test]
+    fn scores_wildcards_under_other_domains_as_names() {
+        let verdict = example().analyse_name("*.paypal.example.com");
+
+        assert!(verdict.wildcard);
+        assert_eq!(verdict.display_domain(), "*.paypal.example.com");
+        assert_eq!(verdict.score, 59);
+        assert!(verdict
+            .signals
+            .iter()
+            .all(|signal| signal.kind != SignalKind::WildcardLookalike));
+    }
+
+    #[test]
+    fn analyses_a_certstream_frame() {
+        let frame = include_str!("../tests/fixtures/certificate_update.json");
+        let message: CertString = serde_json::from_str(frame).unwrap();
+
+        let finding = nettfiske().analyse_certificate(&message.data);
+
+        assert!(finding.is_suspicious());
+        assert_eq!(finding.identity.as_deref(), Some("paypal"));
+        assert_eq!(finding.verdicts.len(), 2);
+        assert_eq!(finding.score, 59);
+        let signals: Vec<(SignalKind, &str, usize)> = finding
+            .signals
+            .iter()
+            .map(|signal| (signal.kind, signal.label.as_str(), signal.points))
+            .collect();
+        assert_eq!(signals, vec![(SignalKind::BrandedNames, "2 names", 10)]);
+        assert_eq!(finding.signals[0].identity.as_deref(), Some("paypal"));
+        let certificate = finding.certificate.unwrap();
+        assert_eq!(certificate.not_after, Some(1_517_374_061));
+        assert_eq!(certificate.cert_index, Some(19_587_936));
+    }
+
+    #[test]
+    fn scores_certificates_mixing_brands() {
+        let frame = include_str!("../tests/fixtures/certificate_update.json");
+        let mut message: CertString = serde_json::from_str(frame).unwrap();
+        message.data.leaf_cert.all_domains = vec![
+            "paypal-login.com".to_string(),
+            "coinbase-wallet.net".to_string(),
+            "example.org".to_string(),
+        ];
+
+        let finding = nettfiske().analyse_certificate(&message.data);
+
+        assert_eq!(finding.identity.as_deref(), Some("paypal"));
+        assert_eq!(finding.verdicts.len(), 2);
+        assert_eq!(finding.score, 70);
+        let signals: Vec<(SignalKind, &str, usize)> = finding
+            .signals
+            .iter()
+            .map(|signal| (signal.kind, signal.label.as_str(), signal.points))
+            .collect();
+        assert_eq!(
+            signals,
+            vec![
+                (SignalKind::BrandedNames, "2 names", 10),
+                (SignalKind::MixedBrands, "coinbase+paypal", 20),
+            ]
+        );
     }
 }
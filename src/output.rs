@@ -0,0 +1,185 @@
+use crate::verdict::{CertificateDetails, Finding, Severity, Signal, Verdict};
+use chrono::{SecondsFormat, Utc};
+
+// How findings are written to stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    // Coloured, human readable lines
+    #[default]
+    Text,
+    // One JSON object per finding and line
+    Jsonl,
+}
+
+impl OutputFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(OutputFormat::Text),
+            "jsonl" => Some(OutputFormat::Jsonl),
+            _ => None,
+        }
+    }
+}
+
+// JSON form of a finding
+#[derive(Serialize, Debug)]
+pub struct FindingRecord<'a> {
+    // When the finding was reported, RFC 3339 in UTC
+    pub timestamp: String,
+    // Decoded domain, with "*." for wildcards
+    pub domain: String,
+    // Domain as it appears on the certificate when it contains punycode
+    pub punycode: Option<&'a str>,
+    pub score: usize,
+    pub severity: Severity,
+    pub identity: Option<&'a str>,
+    // Signals of the primary name followed by the certificate level ones
+    pub signals: Vec<&'a Signal>,
+    // Further matching names on the certificate
+    pub names: Vec<NameRecord<'a>>,
+    pub duplicates: usize,
+    pub certificate: Option<&'a CertificateDetails>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct NameRecord<'a> {
+    pub domain: String,
+    pub punycode: Option<&'a str>,
+    pub score: usize,
+    pub identity: Option<&'a str>,
+}
+
+impl<'a> FindingRecord<'a> {
+    pub fn new(finding: &'a Finding) -> Option<Self> {
+        let (primary, others) = finding.verdicts.split_first()?;
+
+        Some(FindingRecord {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            domain: primary.display_domain(),
+            punycode: punycode(primary),
+            score: finding.score,
+            severity: finding.severity,
+            identity: finding.identity.as_deref(),
+            signals: primary
+                .signals
+                .iter()
+                .chain(finding.signals.iter())
+                .collect(),
+            names: others
+                .iter()
+                .map(|verdict| NameRecord {
+                    domain: verdict.display_domain(),
+                    punycode: punycode(verdict),
+                    score: verdict.score,
+                    identity: verdict.identity.as_deref(),
+                })
+                .collect(),
+            duplicates: primary.duplicates,
+            certificate: finding.certificate.as_ref(),
+        })
+    }
+}
+
+fn punycode(verdict: &Verdict) -> Option<&str> {
+    if verdict.punycode_detected {
+        Some(verdict.original_domain.as_str())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::CertString;
+    use crate::verdict::SignalKind;
+    use chrono::DateTime;
+    use serde_json::json;
+
+    fn finding() -> Finding {
+        let mut homoglyph = Verdict::new("xn--pypal-4ve.com", "paypal.com", true);
+        homoglyph.add_signal(SignalKind::EditDistance, "paypal", Some("paypal"), 90);
+        homoglyph.identity = Some("paypal".to_string());
+        homoglyph.severity = Severity::Critical;
+        homoglyph.duplicates = 2;
+
+        let mut login = Verdict::new("paypal-login.com", "paypal-login.com", false);
+        login.add_keyword_signal(
+            SignalKind::Keyword,
+            "paypal-login",
+            Some("paypal"),
+            "paypal",
+            40,
+        );
+        login.identity = Some("paypal".to_string());
+
+        let mut finding = Finding::new(vec![login, homoglyph]);
+        finding.add_signal(SignalKind::BrandedNames, "2 names", Some("paypal"), 10);
+
+        let frame = include_str!("../tests/fixtures/certificate_update.json");
+        let message: CertString = serde_json::from_str(frame).unwrap();
+        finding.certificate = Some(CertificateDetails::from(&message.data));
+        finding
+    }
+
+    #[test]
+    fn serialises_findings() {
+        let finding = finding();
+        let mut record = serde_json::to_value(FindingRecord::new(&finding).unwrap()).unwrap();
+
+        let timestamp = record["timestamp"].take();
+        assert!(DateTime::parse_from_rfc3339(timestamp.as_str().unwrap()).is_ok());
+        let certificate = record["certificate"].take();
+        assert_eq!(
+            certificate["fingerprint"],
+            "6E:8E:C1:26:5D:9C:B3:31:BC:39:3F:69:10:D7:FA:52:9F:7A:A0:5F"
+        );
+        assert_eq!(certificate["subject"], "/CN=paypal-login.com");
+        assert_eq!(certificate["not_after"], 1_517_374_061);
+        assert_eq!(certificate["cert_index"], 19_587_936);
+        assert_eq!(certificate["source_name"], "Comodo 'Sabre' CT log");
+
+        assert_eq!(
+            record,
+            json!({
+                "timestamp": null,
+                "domain": "paypal.com",
+                "punycode": "xn--pypal-4ve.com",
+                "score": 100,
+                "severity": "critical",
+                "identity": "paypal",
+                "signals": [
+                    {
+                        "kind": "edit_distance",
+                        "label": "paypal",
+                        "identity": "paypal",
+                        "keyword": null,
+                        "points": 90
+                    },
+                    {
+                        "kind": "branded_names",
+                        "label": "2 names",
+                        "identity": "paypal",
+                        "keyword": null,
+                        "points": 10
+                    }
+                ],
+                "names": [
+                    {
+                        "domain": "paypal-login.com",
+                        "punycode": null,
+                        "score": 40,
+                        "identity": "paypal"
+                    }
+                ],
+                "duplicates": 2,
+                "certificate": null
+            })
+        );
+    }
+
+    #[test]
+    fn skips_findings_without_names() {
+        assert!(FindingRecord::new(&Finding::new(Vec::new())).is_none());
+    }
+}
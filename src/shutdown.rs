@@ -0,0 +1,51 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How often a wait checks whether shutdown was requested
+static POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+// Set by SIGINT and SIGTERM. The certstream loop stops, so open files are
+// flushed and finished on the way out instead of losing their buffered tail.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+fn request() {
+    REQUESTED.store(true, Ordering::SeqCst);
+}
+
+// A second signal terminates at once, in case shutting down hangs
+#[cfg(unix)]
+pub fn install() {
+    extern "C" fn handle(signal: libc::c_int) {
+        request();
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+        }
+    }
+
+    let handler = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn install() {}
+
+// Sleeps for the duration, returns early when shutdown is requested
+pub fn sleep(duration: Duration) {
+    let deadline = Instant::now() + duration;
+
+    while !requested() {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        thread::sleep(POLL_INTERVAL.min(deadline - now));
+    }
+}
@@ -0,0 +1,547 @@
+use crate::data::{ScoringConfig, SeverityTier};
+use crate::errors::*;
+use crate::output::FindingRecord;
+use crate::syslog::{Syslog, SyslogConfig};
+use crate::verdict::{Finding, Severity};
+use crate::webhook::{Webhook, WebhookConfig};
+use console::{style, Style};
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+
+// Receives the reported findings, e.g. to print, forward or store them
+pub trait Sink: Send {
+    fn send(&mut self, finding: &Finding);
+}
+
+// Which findings a sink receives, on top of the severity tier sinks lists
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct SinkFilter {
+    pub min_severity: Severity,
+    pub min_score: usize,
+    // Only findings imitating one of these identities, all when empty
+    pub identities: Vec<String>,
+    // Only findings containing punycode
+    pub punycode_only: bool,
+}
+
+impl SinkFilter {
+    pub fn accepts(&self, finding: &Finding) -> bool {
+        if finding.severity < self.min_severity || finding.score < self.min_score {
+            return false;
+        }
+        if self.punycode_only && !finding.punycode_detected() {
+            return false;
+        }
+        if !self.identities.is_empty() {
+            return match finding.identity {
+                Some(ref identity) => self.identities.iter().any(|i| i == identity),
+                None => false,
+            };
+        }
+        true
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SinkKind {
+    // Coloured lines on the console
+    Stdout {
+        #[serde(default)]
+        explain: bool,
+    },
+    // The log file
+    Log {
+        #[serde(default)]
+        explain: bool,
+    },
+    // One JSON object per line, to a file or stdout when no path is set
+    Jsonl {
+        #[serde(default)]
+        path: Option<String>,
+    },
+    Webhook(WebhookConfig),
+    Syslog(SyslogConfig),
+}
+
+impl SinkKind {
+    // Webhooks post medium and more severe findings and syslog low and more
+    // severe ones unless configured otherwise, the other sinks get them all
+    pub fn default_min_severity(&self) -> Severity {
+        match self {
+            SinkKind::Webhook(_) => Severity::Medium,
+            SinkKind::Syslog(_) => Severity::Low,
+            _ => Severity::None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(from = "SinkEntry")]
+pub struct SinkConfig {
+    // Used in the severity tier sinks lists, the type by default
+    pub name: Option<String>,
+    pub filter: SinkFilter,
+    pub kind: SinkKind,
+}
+
+// A sinks list entry as written, min_severity defaults by kind
+#[derive(Deserialize)]
+struct SinkEntry {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    min_severity: Option<Severity>,
+    #[serde(default)]
+    min_score: usize,
+    #[serde(default)]
+    identities: Vec<String>,
+    #[serde(default)]
+    punycode_only: bool,
+    #[serde(flatten)]
+    kind: SinkKind,
+}
+
+impl From<SinkEntry> for SinkConfig {
+    fn from(entry: SinkEntry) -> Self {
+        let min_severity = entry
+            .min_severity
+            .unwrap_or_else(|| entry.kind.default_min_severity());
+
+        SinkConfig {
+            name: entry.name,
+            filter: SinkFilter {
+                min_severity,
+                min_score: entry.min_score,
+                identities: entry.identities,
+                punycode_only: entry.punycode_only,
+            },
+            kind: entry.kind,
+        }
+    }
+}
+
+// Top level webhook or syslog settings, from before the sinks list
+#[derive(Deserialize, Debug, Clone)]
+pub struct LegacySink<T> {
+    #[serde(default)]
+    pub min_severity: Option<Severity>,
+    #[serde(flatten)]
+    pub config: T,
+}
+
+impl SinkConfig {
+    pub fn new(kind: SinkKind) -> Self {
+        SinkConfig {
+            name: None,
+            filter: SinkFilter {
+                min_severity: kind.default_min_severity(),
+                ..SinkFilter::default()
+            },
+            kind,
+        }
+    }
+
+    pub fn from_legacy<T>(legacy: LegacySink<T>, kind: fn(T) -> SinkKind) -> Self {
+        let mut sink = SinkConfig::new(kind(legacy.config));
+        if let Some(min_severity) = legacy.min_severity {
+            sink.filter.min_severity = min_severity;
+        }
+        sink
+    }
+
+    pub fn name(&self) -> &str {
+        match self.name {
+            Some(ref name) => name,
+            None => match self.kind {
+                SinkKind::Stdout { .. } => "stdout",
+                SinkKind::Log { .. } => "log",
+                SinkKind::Jsonl { .. } => "jsonl",
+                SinkKind::Webhook(_) => "webhook",
+                SinkKind::Syslog(_) => "syslog",
+            },
+        }
+    }
+
+    pub fn build(&self, scoring: &ScoringConfig) -> Result<Box<dyn Sink>> {
+        let sink: Box<dyn Sink> = match self.kind {
+            SinkKind::Stdout { explain } => {
+                Box::new(StdoutSink::new(scoring.tiers.clone(), explain))
+            }
+            SinkKind::Log { explain } => Box::new(LogSink::new(explain)),
+            SinkKind::Jsonl { ref path } => match path {
+                Some(path) => Box::new(JsonlSink::to_file(path)?),
+                None => Box::new(JsonlSink::new(Box::new(io::stdout()))),
+            },
+            SinkKind::Webhook(ref config) => Box::new(Webhook::new(config.clone())?),
+            SinkKind::Syslog(ref config) => Box::new(Syslog::new(config.clone())?),
+        };
+        Ok(sink)
+    }
+}
+
+pub fn default_sinks() -> Vec<SinkConfig> {
+    vec![
+        SinkConfig::new(SinkKind::Stdout { explain: false }),
+        SinkConfig::new(SinkKind::Log { explain: false }),
+    ]
+}
+
+struct Registered {
+    name: String,
+    filter: SinkFilter,
+    sink: Box<dyn Sink>,
+}
+
+// Fans each reported finding out to the sinks that accept it
+#[derive(Default)]
+pub struct Dispatcher {
+    sinks: Vec<Registered>,
+}
+
+impl Dispatcher {
+    pub fn new() -> Self {
+        Dispatcher::default()
+    }
+
+    // Sinks that can't be set up are left out with an error
+    pub fn from_config(configs: &[SinkConfig], scoring: &ScoringConfig) -> Self {
+        let mut dispatcher = Dispatcher::new();
+
+        for config in configs {
+            match config.build(scoring) {
+                Ok(sink) => dispatcher.register(config.name(), config.filter.clone(), sink),
+                Err(e) => error!("Unable to set up the {} sink: {}", config.name(), e),
+            }
+        }
+
+        dispatcher
+    }
+
+    pub fn register(&mut self, name: &str, filter: SinkFilter, sink: Box<dyn Sink>) {
+        self.sinks.push(Registered {
+            name: name.to_string(),
+            filter,
+            sink,
+        });
+    }
+
+    pub fn dispatch(&mut self, finding: &Finding, tier: &SeverityTier) {
+        for registered in &mut self.sinks {
+            if tier.has_sink(&registered.name) && registered.filter.accepts(finding) {
+                registered.sink.send(finding);
+            }
+        }
+    }
+}
+
+pub struct StdoutSink {
+    tiers: Vec<SeverityTier>,
+    explain: bool,
+}
+
+impl StdoutSink {
+    pub fn new(tiers: Vec<SeverityTier>, explain: bool) -> Self {
+        StdoutSink { tiers, explain }
+    }
+}
+
+impl Sink for StdoutSink {
+    fn send(&mut self, finding: &Finding) {
+        let verdict = match finding.primary() {
+            Some(verdict) => verdict,
+            None => return,
+        };
+        let tier = self
+            .tiers
+            .iter()
+            .find(|tier| tier.severity == finding.severity);
+        let colour = tier
+            .map(|tier| Style::from_dotted_str(&tier.colour))
+            .unwrap_or_default();
+        let domain_original = &verdict.original_domain;
+        let domain = if tier.is_some_and(|tier| tier.show_original) {
+            domain_original.clone()
+        } else {
+            verdict.display_domain()
+        };
+
+        if finding.severity == Severity::Critical && verdict.punycode_detected {
+            println!(
+                "Homoglyph detected {} (Punycode: {})",
+                colour.apply_to(&domain),
+                domain_original
+            );
+        } else {
+            println!(
+                "Suspicious {} (score {})",
+                colour.apply_to(&domain),
+                finding.score
+            );
+        }
+
+        let others = other_names(finding);
+        if !others.is_empty() {
+            println!("    also on the certificate: {}", others.join(", "));
+        }
+        if let Some(cert_link) = finding
+            .certificate
+            .as_ref()
+            .and_then(|certificate| certificate.cert_link.as_ref())
+        {
+            println!("    {}", style(format!("CT log entry {}", cert_link)).dim());
+        }
+        if self.explain {
+            println!("    {}", style(finding.breakdown()).dim());
+        }
+        if verdict.duplicates > 0 {
+            println!(
+                "    {}",
+                style(format!(
+                    "seen {} more times since last reported",
+                    verdict.duplicates
+                ))
+                .dim()
+            );
+        }
+    }
+}
+
+pub struct LogSink {
+    explain: bool,
+}
+
+impl LogSink {
+    pub fn new(explain: bool) -> Self {
+        LogSink { explain }
+    }
+}
+
+impl Sink for LogSink {
+    fn send(&mut self, finding: &Finding) {
+        let verdict = match finding.primary() {
+            Some(verdict) => verdict,
+            None => return,
+        };
+        let domain = &verdict.display_domain();
+
+        if verdict.punycode_detected {
+            info!("{} - (Punycode: {})", domain, verdict.original_domain);
+        } else {
+            info!("{}", domain);
+        }
+
+        let others = other_names(finding);
+        if !others.is_empty() {
+            info!("{} also on the certificate: {}", domain, others.join(", "));
+        }
+        if let Some(ref certificate) = finding.certificate {
+            info!(
+                "{} certificate {} issued by {}, CT log entry {}",
+                domain,
+                certificate.fingerprint.as_deref().unwrap_or("-"),
+                certificate.issuer.as_deref().unwrap_or("-"),
+                certificate.cert_link.as_deref().unwrap_or("-")
+            );
+        }
+        if self.explain {
+            info!("{} breakdown: {}", domain, finding.breakdown());
+        }
+        if verdict.duplicates > 0 {
+            info!(
+                "{} seen {} more times since last reported",
+                domain, verdict.duplicates
+            );
+        }
+    }
+}
+
+pub struct JsonlSink {
+    writer: Box<dyn Write + Send>,
+}
+
+impl JsonlSink {
+    pub fn new(writer: Box<dyn Write + Send>) -> Self {
+        JsonlSink { writer }
+    }
+
+    // Appends to the file
+    pub fn to_file(path: &str) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .chain_err(|| format!("Unable to open {}", path))?;
+        Ok(JsonlSink::new(Box::new(file)))
+    }
+}
+
+impl Sink for JsonlSink {
+    fn send(&mut self, finding: &Finding) {
+        let record = match FindingRecord::new(finding) {
+            Some(record) => record,
+            None => return,
+        };
+
+        let line = match serde_json::to_string(&record) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("Unable to serialise finding {}: {}", record.domain, e);
+                return;
+            }
+        };
+
+        if let Err(e) = writeln!(self.writer, "{}", line).and_then(|_| self.writer.flush()) {
+            error!("Unable to write finding {}: {}", record.domain, e);
+        }
+    }
+}
+
+fn other_names(finding: &Finding) -> Vec<String> {
+    finding
+        .verdicts
+        .iter()
+        .skip(1)
+        .map(|other| other.display_domain())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verdict::Verdict;
+    use std::sync::{Arc, Mutex};
+
+    // Records the primary domain of every finding it receives
+    struct Recording {
+        received: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Sink for Recording {
+        fn send(&mut self, finding: &Finding) {
+            let domain = finding.primary().unwrap().display_domain();
+            self.received.lock().unwrap().push(domain);
+        }
+    }
+
+    fn recording() -> (Box<dyn Sink>, Arc<Mutex<Vec<String>>>) {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = Recording {
+            received: received.clone(),
+        };
+        (Box::new(sink), received)
+    }
+
+    fn tier(severity: Severity, sinks: Option<&[&str]>) -> SeverityTier {
+        SeverityTier {
+            severity,
+            threshold: 0,
+            colour: String::new(),
+            requires_punycode: false,
+            show_original: false,
+            sinks: sinks.map(|sinks| sinks.iter().map(|sink| sink.to_string()).collect()),
+        }
+    }
+
+    fn finding(domain: &str, score: usize, severity: Severity, identity: &str) -> Finding {
+        let mut verdict = Verdict::scored(domain, score, severity);
+        verdict.identity = Some(identity.to_string());
+        verdict.punycode_detected = domain.starts_with("xn--");
+        Finding::from(verdict)
+    }
+
+    #[test]
+    fn dispatches_to_the_sinks_that_accept_a_finding() {
+        let mut dispatcher = Dispatcher::new();
+        let (all, all_received) = recording();
+        dispatcher.register("all", SinkFilter::default(), all);
+        let (alerts, alerts_received) = recording();
+        let alerts_filter = SinkFilter {
+            min_severity: Severity::High,
+            identities: vec!["paypal".to_string()],
+            ..SinkFilter::default()
+        };
+        dispatcher.register("alerts", alerts_filter, alerts);
+        let (homoglyphs, homoglyphs_received) = recording();
+        let homoglyphs_filter = SinkFilter {
+            punycode_only: true,
+            ..SinkFilter::default()
+        };
+        dispatcher.register("homoglyphs", homoglyphs_filter, homoglyphs);
+        let (scored, scored_received) = recording();
+        let scored_filter = SinkFilter {
+            min_score: 80,
+            ..SinkFilter::default()
+        };
+        dispatcher.register("scored", scored_filter, scored);
+
+        let any_sink = tier(Severity::High, None);
+        dispatcher.dispatch(
+            &finding("paypal-login.com", 60, Severity::Low, "paypal"),
+            &any_sink,
+        );
+        dispatcher.dispatch(
+            &finding("coinbase-login.com", 95, Severity::High, "coinbase"),
+            &any_sink,
+        );
+        dispatcher.dispatch(
+            &finding("paypal-verify.com", 92, Severity::High, "paypal"),
+            &any_sink,
+        );
+        // The tier only sends to the listed sinks, each still applies its filter
+        let listed = tier(Severity::Critical, Some(&["alerts", "homoglyphs"]));
+        dispatcher.dispatch(
+            &finding("xn--pypal-4ve.com", 130, Severity::Critical, "paypal"),
+            &listed,
+        );
+
+        assert_eq!(
+            *all_received.lock().unwrap(),
+            [
+                "paypal-login.com",
+                "coinbase-login.com",
+                "paypal-verify.com"
+            ]
+        );
+        assert_eq!(
+            *alerts_received.lock().unwrap(),
+            ["paypal-verify.com", "xn--pypal-4ve.com"]
+        );
+        assert_eq!(*homoglyphs_received.lock().unwrap(), ["xn--pypal-4ve.com"]);
+        assert_eq!(
+            *scored_received.lock().unwrap(),
+            ["coinbase-login.com", "paypal-verify.com"]
+        );
+    }
+
+    fn sink(json: &str) -> SinkConfig {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn defaults_min_severity_by_kind() {
+        let webhook = sink(r#"{"type": "webhook", "url": "http://localhost/"}"#);
+        assert_eq!(webhook.filter.min_severity, Severity::Medium);
+        assert_eq!(webhook.name(), "webhook");
+
+        let syslog = sink(r#"{"type": "syslog"}"#);
+        assert_eq!(syslog.filter.min_severity, Severity::Low);
+
+        let stdout = sink(r#"{"type": "stdout", "explain": true}"#);
+        assert_eq!(stdout.filter.min_severity, Severity::None);
+    }
+
+    #[test]
+    fn keeps_configured_filters() {
+        let webhook = sink(
+            r#"{"type": "webhook", "name": "slack", "url": "http://localhost/",
+                "min_severity": "info", "min_score": 60, "identities": ["paypal"]}"#,
+        );
+
+        assert_eq!(webhook.name(), "slack");
+        assert_eq!(webhook.filter.min_severity, Severity::Info);
+        assert_eq!(webhook.filter.min_score, 60);
+        assert_eq!(webhook.filter.identities, ["paypal"]);
+    }
+}
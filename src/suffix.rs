@@ -0,0 +1,65 @@
+use crate::data::PublicSuffixConfig;
+use publicsuffix::List;
+use std::sync::{Arc, RwLock, Weak};
+use std::{thread, time};
+
+// Snapshot of https://publicsuffix.org/list/public_suffix_list.dat shipped with the binary
+static BUNDLED_LIST: &str = include_str!("../data/public_suffix_list.dat");
+
+pub type SharedList = Arc<RwLock<List>>;
+
+pub fn bundled_list() -> List {
+    List::from_str(BUNDLED_LIST).expect("bundled public suffix list is valid")
+}
+
+// Loads the list from the configured file, falling back to the bundled snapshot
+pub fn load_list(config: &PublicSuffixConfig) -> List {
+    if let Some(ref path) = config.path {
+        match List::from_path(path) {
+            Ok(list) => return list,
+            Err(e) => error!("Unable to load public suffix list {}: {}", path, e),
+        }
+    }
+
+    bundled_list()
+}
+
+fn fetch_list(config: &PublicSuffixConfig) -> publicsuffix::errors::Result<List> {
+    match config.url {
+        Some(ref url) => List::from_url(url.as_str()),
+        None => List::fetch(),
+    }
+}
+
+// Downloads a fresh list every refresh_hours and swaps it in. The current list is
+// kept when the download fails. The thread ends once the list is no longer used.
+pub fn spawn_refresh(list: &SharedList, config: &PublicSuffixConfig) {
+    let hours = match config.refresh_hours {
+        Some(hours) if hours > 0 => hours,
+        _ => return,
+    };
+
+    let list: Weak<RwLock<List>> = Arc::downgrade(list);
+    let config = config.clone();
+    let interval = time::Duration::from_secs(hours * 60 * 60);
+
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+
+        let shared = match list.upgrade() {
+            Some(shared) => shared,
+            None => break,
+        };
+
+        match fetch_list(&config) {
+            Ok(fresh) => {
+                *shared.write().unwrap() = fresh;
+                info!("Public suffix list refreshed");
+            }
+            Err(e) => warn!(
+                "Unable to refresh public suffix list, keeping the current one: {}",
+                e
+            ),
+        }
+    });
+}
@@ -0,0 +1,503 @@
+use crate::errors::*;
+use crate::net;
+use crate::sink::Sink;
+use crate::verdict::{Finding, Severity};
+use chrono::{SecondsFormat, Utc};
+use std::io::Write;
+use std::net::{TcpStream, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::process;
+use std::time::{Duration, Instant};
+
+const VENDOR: &str = "Nettfiske";
+const PRODUCT: &str = "Nettfiske";
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+// Findings are sent while detection waits, a dead server must not hold it up
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+// Facilities 0 (kern) to 23 (local7)
+pub const MAX_FACILITY: u8 = 23;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogTransport {
+    Udp,
+    // Octet counted frames (RFC 6587)
+    Tcp,
+    // Datagram socket, e.g. /dev/log
+    Unix,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SyslogFormat {
+    // ArcSight Common Event Format
+    Cef,
+    // QRadar Log Event Extended Format
+    Leef,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SyslogConfig {
+    pub transport: SyslogTransport,
+    // host:port, or the socket path for unix
+    pub address: String,
+    pub format: SyslogFormat,
+    // Syslog facility number, 16 is local0
+    pub facility: u8,
+    pub app_name: String,
+    // Sent as the HOSTNAME field, the local host name when not set
+    pub hostname: Option<String>,
+}
+
+impl Default for SyslogConfig {
+    fn default() -> Self {
+        SyslogConfig {
+            transport: SyslogTransport::Udp,
+            address: "127.0.0.1:514".to_string(),
+            format: SyslogFormat::Cef,
+            facility: 16,
+            app_name: "nettfiske".to_string(),
+            hostname: None,
+        }
+    }
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    Tcp(Option<TcpStream>),
+    #[cfg(unix)]
+    Unix(UnixDatagram),
+}
+
+// Sends findings as RFC 5424 syslog messages with a CEF or LEEF payload
+pub struct Syslog {
+    config: SyslogConfig,
+    hostname: String,
+    connection: Connection,
+    // No connection attempts before then after one failed, findings are dropped
+    retry_at: Option<Instant>,
+}
+
+impl Syslog {
+    pub fn new(config: SyslogConfig) -> Result<Self> {
+        if config.facility > MAX_FACILITY {
+            bail!(
+                "Invalid syslog facility {}, it must be 0 to {}",
+                config.facility,
+                MAX_FACILITY
+            );
+        }
+
+        let connection = match config.transport {
+            SyslogTransport::Udp => {
+                let socket = UdpSocket::bind("0.0.0.0:0")?;
+                socket
+                    .connect(&config.address)
+                    .chain_err(|| format!("Unable to reach syslog at {}", config.address))?;
+                Connection::Udp(socket)
+            }
+            // Connected on the first message, and again after a failure
+            SyslogTransport::Tcp => Connection::Tcp(None),
+            #[cfg(unix)]
+            SyslogTransport::Unix => {
+                let socket = UnixDatagram::unbound()?;
+                socket
+                    .connect(&config.address)
+                    .chain_err(|| format!("Unable to reach syslog at {}", config.address))?;
+                Connection::Unix(socket)
+            }
+            #[cfg(not(unix))]
+            SyslogTransport::Unix => bail!("Unix sockets are not supported on this platform"),
+        };
+
+        let hostname = match config.hostname {
+            Some(ref hostname) => hostname.clone(),
+            None => hostname::get()
+                .ok()
+                .and_then(|name| name.into_string().ok())
+                .unwrap_or_else(|| "-".to_string()),
+        };
+
+        Ok(Syslog {
+            config,
+            hostname,
+            connection,
+            retry_at: None,
+        })
+    }
+
+    fn write(&mut self, message: &[u8]) -> Result<()> {
+        match self.connection {
+            Connection::Udp(ref socket) => {
+                socket.send(message)?;
+            }
+            #[cfg(unix)]
+            Connection::Unix(ref socket) => {
+                socket.send(message)?;
+            }
+            Connection::Tcp(ref mut stream) => {
+                let address = &self.config.address;
+                let mut frame = format!("{} ", message.len()).into_bytes();
+                frame.extend_from_slice(message);
+
+                // One reconnect when the server closed the connection
+                for _ in 0..2 {
+                    if stream.is_none() {
+                        if let Some(retry_at) = self.retry_at {
+                            if Instant::now() < retry_at {
+                                bail!("Syslog at {} is unreachable", address);
+                            }
+                        }
+                        let connected = match net::connect_timeout(address, CONNECT_TIMEOUT) {
+                            Ok(connected) => connected,
+                            Err(e) => {
+                                self.retry_at = Some(Instant::now() + RECONNECT_DELAY);
+                                return Err(e).chain_err(|| {
+                                    format!("Unable to reach syslog at {}", address)
+                                });
+                            }
+                        };
+                        self.retry_at = None;
+                        connected.set_write_timeout(Some(WRITE_TIMEOUT))?;
+                        *stream = Some(connected);
+                    }
+
+                    if let Some(ref mut connected) = stream {
+                        match connected.write_all(&frame) {
+                            Ok(()) => return Ok(()),
+                            Err(e) => {
+                                warn!("Syslog connection lost: {}", e);
+                                *stream = None;
+                            }
+                        }
+                    }
+                }
+                bail!("Unable to write to syslog at {}", address);
+            }
+        }
+
+        Ok(())
+    }
+
+    // <PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA MSG
+    fn format(&self, finding: &Finding) -> Option<String> {
+        let payload = match self.config.format {
+            SyslogFormat::Cef => cef(finding)?,
+            SyslogFormat::Leef => leef(finding)?,
+        };
+        let priority = u16::from(self.config.facility) * 8 + syslog_severity(finding.severity);
+
+        Some(format!(
+            "<{}>1 {} {} {} {} finding - {}",
+            priority,
+            Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            header_field(&self.hostname),
+            header_field(&self.config.app_name),
+            process::id(),
+            payload
+        ))
+    }
+}
+
+impl Sink for Syslog {
+    fn send(&mut self, finding: &Finding) {
+        let message = match self.format(finding) {
+            Some(message) => message,
+            None => return,
+        };
+
+        if let Err(e) = self.write(message.as_bytes()) {
+            error!("Unable to send finding to syslog: {}", e);
+        }
+    }
+}
+
+// Fields shared by both formats
+struct Fields<'a> {
+    domain: String,
+    punycode: Option<&'a str>,
+    identity: Option<&'a str>,
+    fingerprint: Option<&'a str>,
+    cert_link: Option<&'a str>,
+    score: usize,
+    homoglyph: bool,
+}
+
+impl<'a> Fields<'a> {
+    fn new(finding: &'a Finding) -> Option<Self> {
+        let primary = finding.primary()?;
+        let certificate = finding.certificate.as_ref();
+
+        Some(Fields {
+            domain: primary.display_domain(),
+            punycode: if primary.punycode_detected {
+                Some(primary.original_domain.as_str())
+            } else {
+                None
+            },
+            identity: finding.identity.as_deref(),
+            fingerprint: certificate.and_then(|c| c.fingerprint.as_deref()),
+            cert_link: certificate.and_then(|c| c.cert_link.as_deref()),
+            score: finding.score,
+            homoglyph: finding.severity == Severity::Critical && primary.punycode_detected,
+        })
+    }
+
+    fn event(&self) -> (&'static str, &'static str) {
+        if self.homoglyph {
+            ("homoglyph", "Homoglyph domain")
+        } else {
+            ("suspicious_domain", "Suspicious domain")
+        }
+    }
+}
+
+// CEF:Version|Vendor|Product|Version|SignatureID|Name|Severity|Extension
+fn cef(finding: &Finding) -> Option<String> {
+    let fields = Fields::new(finding)?;
+    let (event_id, name) = fields.event();
+
+    let mut extension = vec![
+        format!("dhost={}", cef_value(&fields.domain)),
+        "cn1Label=score".to_string(),
+        format!("cn1={}", fields.score),
+    ];
+    let custom = [
+        ("punycode", fields.punycode),
+        ("identity", fields.identity),
+        ("fingerprint", fields.fingerprint),
+        ("certLink", fields.cert_link),
+    ];
+    for (index, (label, value)) in custom.iter().enumerate() {
+        if let Some(value) = value {
+            extension.push(format!("cs{}Label={}", index + 1, label));
+            extension.push(format!("cs{}={}", index + 1, cef_value(value)));
+        }
+    }
+
+    Some(format!(
+        "CEF:0|{}|{}|{}|{}|{}|{}|{}",
+        VENDOR,
+        PRODUCT,
+        VERSION,
+        event_id,
+        name,
+        cef_severity(finding.severity),
+        extension.join(" ")
+    ))
+}
+
+// LEEF:1.0|Vendor|Product|Version|EventID| followed by tab separated attributes
+fn leef(finding: &Finding) -> Option<String> {
+    let fields = Fields::new(finding)?;
+    let (event_id, _) = fields.event();
+
+    let mut attributes = vec![
+        format!("sev={}", cef_severity(finding.severity)),
+        format!("domain={}", leef_value(&fields.domain)),
+        format!("score={}", fields.score),
+    ];
+    let optional = [
+        ("punycode", fields.punycode),
+        ("identity", fields.identity),
+        ("fingerprint", fields.fingerprint),
+        ("certLink", fields.cert_link),
+    ];
+    for (key, value) in optional.iter() {
+        if let Some(value) = value {
+            attributes.push(format!("{}={}", key, leef_value(value)));
+        }
+    }
+
+    Some(format!(
+        "LEEF:1.0|{}|{}|{}|{}|{}",
+        VENDOR,
+        PRODUCT,
+        VERSION,
+        event_id,
+        attributes.join("\t")
+    ))
+}
+
+// RFC 5424 severity, 2 (critical) to 7 (debug)
+fn syslog_severity(severity: Severity) -> u16 {
+    match severity {
+        Severity::Critical => 2,
+        Severity::High => 3,
+        Severity::Medium => 4,
+        Severity::Low => 5,
+        Severity::Info => 6,
+        Severity::None => 7,
+    }
+}
+
+// CEF and LEEF severity, 0 to 10
+fn cef_severity(severity: Severity) -> u8 {
+    match severity {
+        Severity::Critical => 10,
+        Severity::High => 8,
+        Severity::Medium => 5,
+        Severity::Low => 3,
+        Severity::Info => 1,
+        Severity::None => 0,
+    }
+}
+
+fn cef_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn leef_value(value: &str) -> String {
+    value.replace(['\t', '\n', '\r'], " ")
+}
+
+// Printable ASCII without spaces, "-" when empty
+fn header_field(value: &str) -> String {
+    let field: String = value
+        .chars()
+        .filter(|c| c.is_ascii_graphic())
+        .take(48)
+        .collect();
+    if field.is_empty() {
+        "-".to_string()
+    } else {
+        field
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verdict::{CertificateDetails, Verdict};
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    fn finding(severity: Severity) -> Finding {
+        let mut verdict = Verdict::scored("pаypal.com", 130, severity);
+        verdict.original_domain = "xn--pypal-4ve.com".to_string();
+        verdict.punycode_detected = true;
+
+        let mut finding = Finding::from(verdict);
+        finding.certificate = Some(CertificateDetails {
+            fingerprint: Some("6E:8E:C1".to_string()),
+            cert_link: Some("https://crt.sh/?q=a=b".to_string()),
+            ..CertificateDetails::default()
+        });
+        finding
+    }
+
+    fn config(transport: SyslogTransport, address: String, format: SyslogFormat) -> SyslogConfig {
+        SyslogConfig {
+            transport,
+            address,
+            format,
+            hostname: Some("sensor 1".to_string()),
+            ..SyslogConfig::default()
+        }
+    }
+
+    #[test]
+    fn escapes_cef_and_leef_values() {
+        assert_eq!(cef_value("a\\b=c\r\nd|e"), "a\\\\b\\=c\\r\\nd|e");
+        assert_eq!(leef_value("a\tb\r\nc=d"), "a b  c=d");
+    }
+
+    #[test]
+    fn cleans_header_fields() {
+        assert_eq!(header_field("sensor 1.example.com"), "sensor1.example.com");
+        assert_eq!(header_field(" \t"), "-");
+        assert_eq!(header_field(&"a".repeat(60)).len(), 48);
+    }
+
+    #[test]
+    fn formats_cef_findings() {
+        let payload = cef(&finding(Severity::Critical)).unwrap();
+
+        assert_eq!(
+            payload,
+            format!(
+                "CEF:0|Nettfiske|Nettfiske|{}|homoglyph|Homoglyph domain|10|dhost=pаypal.com \
+                 cn1Label=score cn1=130 cs1Label=punycode cs1=xn--pypal-4ve.com \
+                 cs2Label=identity cs2=paypal cs3Label=fingerprint cs3=6E:8E:C1 \
+                 cs4Label=certLink cs4=https://crt.sh/?q\\=a\\=b",
+                VERSION
+            )
+        );
+    }
+
+    #[test]
+    fn formats_leef_findings() {
+        let payload = leef(&finding(Severity::High)).unwrap();
+
+        assert_eq!(
+            payload,
+            format!(
+                "LEEF:1.0|Nettfiske|Nettfiske|{}|suspicious_domain|sev=8\tdomain=pаypal.com\t\
+                 score=130\tpunycode=xn--pypal-4ve.com\tidentity=paypal\tfingerprint=6E:8E:C1\t\
+                 certLink=https://crt.sh/?q=a=b",
+                VERSION
+            )
+        );
+    }
+
+    #[test]
+    fn sends_udp_messages_with_the_priority() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let address = server.local_addr().unwrap().to_string();
+        let mut syslog =
+            Syslog::new(config(SyslogTransport::Udp, address, SyslogFormat::Cef)).unwrap();
+
+        syslog.send(&finding(Severity::Low));
+
+        let mut buffer = [0; 2048];
+        let length = server.recv(&mut buffer).unwrap();
+        let message = String::from_utf8_lossy(&buffer[..length]).to_string();
+        // local0 (16) * 8 + notice (5)
+        assert!(message.starts_with("<133>1 "), "{}", message);
+        assert!(message.contains(" sensor1 nettfiske "), "{}", message);
+        assert!(
+            message.ends_with(&cef(&finding(Severity::Low)).unwrap()),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn frames_tcp_messages_with_their_length() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let mut syslog =
+            Syslog::new(config(SyslogTransport::Tcp, address, SyslogFormat::Leef)).unwrap();
+
+        syslog.send(&finding(Severity::Critical));
+        drop(syslog);
+
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut received = String::new();
+        stream.read_to_string(&mut received).unwrap();
+        let (length, message) = received.split_at(received.find(' ').unwrap());
+        assert_eq!(length.parse::<usize>().unwrap(), message.len() - 1);
+        assert!(message.starts_with(" <130>1 "), "{}", message);
+    }
+
+    #[test]
+    fn rejects_invalid_facilities() {
+        let config = SyslogConfig {
+            facility: 24,
+            ..SyslogConfig::default()
+        };
+
+        assert!(Syslog::new(config).is_err());
+    }
+}
@@ -0,0 +1,129 @@
+use crate::data::Config;
+use crate::sink::SinkKind;
+use crate::syslog::MAX_FACILITY;
+use std::collections::HashSet;
+use std::path::Path;
+
+// Problems in a configuration that parses but would not behave as intended,
+// empty when there are none
+pub fn validate(config: &Config) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if config.identities.is_empty() {
+        problems.push("No identities to look for".to_string());
+    }
+
+    let mut identities = HashSet::new();
+    for identity in &config.identities {
+        let name = identity.common_name.trim();
+        if name.is_empty() {
+            problems.push("Identity without a common_name".to_string());
+            continue;
+        }
+        if !identities.insert(name.to_lowercase()) {
+            problems.push(format!("Identity {} is listed more than once", name));
+        }
+        if identity.weight <= 0.0 {
+            problems.push(format!(
+                "Identity {} has weight {}, it never scores",
+                name, identity.weight
+            ));
+        }
+        if identity.profiles().any(|profile| profile.is_empty()) {
+            problems.push(format!(
+                "Identity {} has a certificate profile without fields, it never matches",
+                name
+            ));
+        }
+    }
+
+    if config.webhook.is_some() {
+        problems.push("The top level webhook setting is deprecated, add it to sinks".to_string());
+    }
+    if config.syslog.is_some() {
+        problems.push("The top level syslog setting is deprecated, add it to sinks".to_string());
+    }
+
+    let mut sinks = HashSet::new();
+    for sink in &config.sinks {
+        let name = sink.name();
+        if !sinks.insert(name) {
+            problems.push(format!("Sink {} is listed more than once", name));
+        }
+        for identity in &sink.filter.identities {
+            if !identities.contains(&identity.trim().to_lowercase()) {
+                problems.push(format!(
+                    "Sink {} filters on the unknown identity {}",
+                    name, identity
+                ));
+            }
+        }
+        match sink.kind {
+            SinkKind::Webhook(ref webhook)
+                if !webhook.url.starts_with("http://") && !webhook.url.starts_with("https://") =>
+            {
+                problems.push(format!("Sink {} needs an http(s) url", name));
+            }
+            SinkKind::Syslog(ref syslog) if syslog.address.trim().is_empty() => {
+                problems.push(format!("Sink {} has no address", name));
+            }
+            SinkKind::Syslog(ref syslog) if syslog.facility > MAX_FACILITY => {
+                problems.push(format!(
+                    "Sink {} has facility {}, it must be 0 to {}",
+                    name, syslog.facility, MAX_FACILITY
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    let scoring = &config.scoring;
+    let mut severities = HashSet::new();
+    for tier in &scoring.tiers {
+        if !severities.insert(tier.severity) {
+            problems.push(format!("Severity {} has more than one tier", tier.severity));
+        }
+        for sink in tier.sinks.iter().flatten() {
+            if !sinks.contains(sink.as_str()) {
+                problems.push(format!(
+                    "The {} tier sends to the unknown sink {}",
+                    tier.severity, sink
+                ));
+            }
+        }
+    }
+    // A more severe tier with a lower threshold hides the less severe one
+    for tier in &scoring.tiers {
+        if let Some(hiding) = scoring.tiers.iter().find(|other| {
+            other.severity > tier.severity
+                && other.threshold <= tier.threshold
+                && (tier.requires_punycode || !other.requires_punycode)
+        }) {
+            problems.push(format!(
+                "The {} tier is never reached, the {} tier starts at {}",
+                tier.severity, hiding.severity, hiding.threshold
+            ));
+        }
+    }
+
+    for rule in &scoring.issuer_rules {
+        if !rule.has_conditions() {
+            problems.push(format!(
+                "Issuer rule {} has no conditions, it never applies",
+                rule.name
+            ));
+        }
+    }
+
+    if let Some(ref path) = config.public_suffix.path {
+        if !Path::new(path).is_file() {
+            problems.push(format!("Public suffix list {} not found", path));
+        }
+    }
+
+    if let Err(e) = config.log.validate() {
+        problems.push(format!("Log: {}", e));
+    }
+
+    problems
+}
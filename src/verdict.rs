@@ -0,0 +1,346 @@
+use crate::data::Data;
+use std::cmp::Reverse;
+use std::fmt;
+
+#[derive(
+    Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    #[default]
+    None,
+    Info,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(Severity::None),
+            "info" => Some(Severity::Info),
+            "low" => Some(Severity::Low),
+            "medium" => Some(Severity::Medium),
+            "high" => Some(Severity::High),
+            "critical" => Some(Severity::Critical),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Severity::None => "none",
+            Severity::Info => "info",
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SignalKind {
+    Keyword,
+    EditDistance,
+    TldOnSubdomain,
+    DeeplyNested,
+    WildcardLookalike,
+    BrandedNames,
+    MixedBrands,
+    IssuerRule,
+}
+
+impl fmt::Display for SignalKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SignalKind::Keyword => "keyword",
+            SignalKind::EditDistance => "edit_distance",
+            SignalKind::TldOnSubdomain => "tld_on_subdomain",
+            SignalKind::DeeplyNested => "deeply_nested",
+            SignalKind::WildcardLookalike => "wildcard_lookalike",
+            SignalKind::BrandedNames => "branded_names",
+            SignalKind::MixedBrands => "mixed_brands",
+            SignalKind::IssuerRule => "issuer_rule",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// A single scoring hit: what fired, on which label, against which identity
+#[derive(Serialize, Debug, Clone)]
+pub struct Signal {
+    pub kind: SignalKind,
+    pub label: String,
+    pub identity: Option<String>,
+    // Identity keyword that matched, when it isn't the identity name itself
+    pub keyword: Option<String>,
+    pub points: usize,
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.kind, self.label)?;
+        if let Some(ref identity) = self.identity {
+            write!(f, " ~ {}", identity)?;
+        }
+        if let Some(ref keyword) = self.keyword {
+            write!(f, " via {}", keyword)?;
+        }
+        write!(f, " (+{})", self.points)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Verdict {
+    // Domain as seen on the certificate
+    pub original_domain: String,
+    // Punycode decoded to its unicode skeleton
+    pub domain: String,
+    pub score: usize,
+    pub punycode_detected: bool,
+    // Identity that contributed the most to the score
+    pub identity: Option<String>,
+    pub severity: Severity,
+    pub signals: Vec<Signal>,
+    // Skipped without scoring because the domain is allowlisted
+    pub allowlisted: bool,
+    // Times the domain was seen again since it was last reported
+    pub duplicates: usize,
+    // Wildcard certificate name, e.g. *.example.com
+    pub wildcard: bool,
+}
+
+impl Verdict {
+    pub fn new(original_domain: &str, domain: &str, punycode_detected: bool) -> Self {
+        Verdict {
+            original_domain: original_domain.to_string(),
+            domain: domain.to_string(),
+            score: 0,
+            punycode_detected,
+            identity: None,
+            severity: Severity::None,
+            signals: Vec::new(),
+            allowlisted: false,
+            duplicates: 0,
+            wildcard: false,
+        }
+    }
+
+    // Records a hit and adds its points to the score, hits worth nothing are dropped
+    pub fn add_signal(
+        &mut self, kind: SignalKind, label: &str, identity: Option<&str>, points: usize,
+    ) {
+        self.push_signal(kind, label, identity, None, points);
+    }
+
+    // Same as add_signal, for a hit on one of the identity keywords
+    pub fn add_keyword_signal(
+        &mut self, kind: SignalKind, label: &str, identity: Option<&str>, keyword: &str,
+        points: usize,
+    ) {
+        let keyword = Some(keyword).filter(|keyword| Some(*keyword) != identity);
+        self.push_signal(kind, label, identity, keyword, points);
+    }
+
+    fn push_signal(
+        &mut self, kind: SignalKind, label: &str, identity: Option<&str>, keyword: Option<&str>,
+        points: usize,
+    ) {
+        if points == 0 {
+            return;
+        }
+
+        self.score += points;
+        self.signals.push(Signal {
+            kind,
+            label: label.to_string(),
+            identity: identity.map(|i| i.to_string()),
+            keyword: keyword.map(|k| k.to_string()),
+            points,
+        });
+    }
+
+    // Human readable score breakdown, e.g. "keyword facebook ~ facebook (+50), ..."
+    pub fn breakdown(&self) -> String {
+        self.signals
+            .iter()
+            .map(|signal| signal.to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    // Decoded domain, keeping the wildcard of wildcard certificates
+    pub fn display_domain(&self) -> String {
+        if self.wildcard {
+            format!("*.{}", self.domain)
+        } else {
+            self.domain.clone()
+        }
+    }
+
+    pub fn is_suspicious(&self) -> bool {
+        self.severity > Severity::None
+    }
+}
+
+// What gets reported: a certificate, or a bare domain, with all of its names that
+// matched an identity. The score is the one of the most suspicious name plus the
+// certificate level signals.
+#[derive(Debug, Clone)]
+pub struct Finding {
+    // Matching names, most suspicious first
+    pub verdicts: Vec<Verdict>,
+    // Certificate level signals
+    pub signals: Vec<Signal>,
+    pub score: usize,
+    pub severity: Severity,
+    pub identity: Option<String>,
+    // Number of names on the certificate
+    pub total_names: usize,
+    // Not set for bare domains
+    pub certificate: Option<CertificateDetails>,
+}
+
+// Certificate metadata carried into the reports, enough to look up the CT log entry
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct CertificateDetails {
+    pub fingerprint: Option<String>,
+    pub serial_number: Option<String>,
+    pub subject: Option<String>,
+    pub issuer: Option<String>,
+    pub not_before: Option<i64>,
+    pub not_after: Option<i64>,
+    pub seen: Option<f64>,
+    pub cert_index: Option<u64>,
+    pub cert_link: Option<String>,
+    pub source_name: Option<String>,
+    pub source_url: Option<String>,
+}
+
+impl From<&Data> for CertificateDetails {
+    fn from(data: &Data) -> Self {
+        let leaf_cert = &data.leaf_cert;
+
+        CertificateDetails {
+            fingerprint: leaf_cert.fingerprint.clone(),
+            serial_number: leaf_cert.serial_number.clone(),
+            subject: Some(leaf_cert.subject.aggregated.clone()),
+            issuer: leaf_cert
+                .issuer
+                .as_ref()
+                .map(|issuer| issuer.aggregated.clone()),
+            not_before: leaf_cert.not_before,
+            not_after: leaf_cert.not_after,
+            seen: data.seen,
+            cert_index: data.cert_index,
+            cert_link: data.cert_link.clone(),
+            source_name: data.source.as_ref().map(|source| source.name.clone()),
+            source_url: data.source.as_ref().map(|source| source.url.clone()),
+        }
+    }
+}
+
+impl Finding {
+    pub fn new(verdicts: Vec<Verdict>) -> Self {
+        let total_names = verdicts.len();
+
+        let mut verdicts: Vec<Verdict> = verdicts
+            .into_iter()
+            .filter(|verdict| verdict.identity.is_some() || verdict.is_suspicious())
+            .collect();
+        verdicts.sort_by_key(|verdict| Reverse(verdict.score));
+
+        let (score, severity, identity) = match verdicts.first() {
+            Some(top) => (top.score, top.severity, top.identity.clone()),
+            None => (0, Severity::None, None),
+        };
+
+        Finding {
+            verdicts,
+            signals: Vec::new(),
+            score,
+            severity,
+            identity,
+            total_names,
+            certificate: None,
+        }
+    }
+
+    pub fn add_signal(
+        &mut self, kind: SignalKind, label: &str, identity: Option<&str>, points: usize,
+    ) {
+        if points == 0 {
+            return;
+        }
+
+        self.score += points;
+        self.signals.push(Signal {
+            kind,
+            label: label.to_string(),
+            identity: identity.map(|i| i.to_string()),
+            keyword: None,
+            points,
+        });
+    }
+
+    // The most suspicious name
+    pub fn primary(&self) -> Option<&Verdict> {
+        self.verdicts.first()
+    }
+
+    pub fn punycode_detected(&self) -> bool {
+        self.verdicts
+            .iter()
+            .any(|verdict| verdict.punycode_detected)
+    }
+
+    // Breakdown of the primary name followed by the certificate level signals
+    pub fn breakdown(&self) -> String {
+        let mut parts: Vec<String> = Vec::new();
+
+        if let Some(primary) = self.primary() {
+            parts.extend(primary.signals.iter().map(|signal| signal.to_string()));
+        }
+        parts.extend(self.signals.iter().map(|signal| signal.to_string()));
+
+        parts.join(", ")
+    }
+
+    pub fn is_suspicious(&self) -> bool {
+        self.severity > Severity::None && !self.verdicts.is_empty()
+    }
+}
+
+#[cfg(test)]
+impl Verdict {
+    // A name already scored against paypal, for what happens after scoring
+    pub(crate) fn scored(domain: &str, score: usize, severity: Severity) -> Self {
+        let mut verdict = Verdict::new(domain, domain, false);
+        verdict.score = score;
+        verdict.severity = severity;
+        verdict.identity = Some("paypal".to_string());
+        verdict
+    }
+}
+
+// A bare domain, kept even when it matches nothing
+impl From<Verdict> for Finding {
+    fn from(verdict: Verdict) -> Self {
+        Finding {
+            score: verdict.score,
+            severity: verdict.severity,
+            identity: verdict.identity.clone(),
+            verdicts: vec![verdict],
+            signals: Vec::new(),
+            total_names: 1,
+            certificate: None,
+        }
+    }
+}
@@ -0,0 +1,436 @@
+use crate::backoff::{Backoff, BackoffConfig};
+use crate::errors::*;
+use crate::output::FindingRecord;
+use crate::sink::Sink;
+use crate::verdict::Finding;
+use native_tls::TlsConnector;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::mpsc::{channel, sync_channel, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookTemplate {
+    // {"text": "..."}, accepted by Slack and Mattermost incoming webhooks
+    Slack,
+    // {"findings": [...]}, the objects written by --output jsonl
+    Json,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct WebhookConfig {
+    pub url: String,
+    pub template: WebhookTemplate,
+    // Extra request headers, e.g. authentication
+    pub headers: BTreeMap<String, String>,
+    // Findings posted together in one request
+    pub batch_size: usize,
+    // Longest a finding waits for its batch to fill up
+    pub batch_interval_secs: u64,
+    // 0 means no limit
+    pub max_requests_per_minute: u32,
+    // Failed requests are retried with this backoff, then the batch is dropped
+    pub retry: BackoffConfig,
+    pub max_retries: u32,
+    pub timeout_secs: u64,
+    // Findings waiting to be posted, new findings are dropped when it is full
+    pub queue_size: usize,
+    // Longest wait on exit for the queued findings to be posted
+    pub shutdown_timeout_secs: u64,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            url: String::new(),
+            template: WebhookTemplate::Json,
+            headers: BTreeMap::new(),
+            batch_size: 10,
+            batch_interval_secs: 5,
+            max_requests_per_minute: 30,
+            retry: BackoffConfig::default(),
+            max_retries: 5,
+            timeout_secs: 10,
+            queue_size: 1000,
+            shutdown_timeout_secs: 30,
+        }
+    }
+}
+
+struct Entry {
+    record: Value,
+    summary: String,
+}
+
+// Posts findings to an HTTP endpoint from a background thread, so a slow or
+// unreachable endpoint never holds up the certstream. Dropping the webhook posts
+// what is still queued, for up to shutdown_timeout_secs.
+pub struct Webhook {
+    sender: Option<SyncSender<Entry>>,
+    worker: Option<JoinHandle<()>>,
+    // Signalled when the worker is done
+    done: Receiver<()>,
+    shutdown_timeout: Duration,
+}
+
+impl Webhook {
+    pub fn new(config: WebhookConfig) -> Result<Self> {
+        if config.url.is_empty() {
+            bail!("The webhook has no url");
+        }
+
+        let tls = TlsConnector::new().chain_err(|| "Unable to set up TLS")?;
+        let agent = ureq::AgentBuilder::new()
+            .tls_connector(Arc::new(tls))
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build();
+
+        let shutdown_timeout = Duration::from_secs(config.shutdown_timeout_secs);
+        let (sender, receiver) = sync_channel(config.queue_size.max(1));
+        let (done_sender, done) = channel();
+        let worker = thread::Builder::new()
+            .name("webhook".to_string())
+            .spawn(move || {
+                Worker::new(config, agent).run(receiver);
+                let _ = done_sender.send(());
+            })?;
+
+        Ok(Webhook {
+            sender: Some(sender),
+            worker: Some(worker),
+            done,
+            shutdown_timeout,
+        })
+    }
+}
+
+impl Sink for Webhook {
+    fn send(&mut self, finding: &Finding) {
+        let record = match FindingRecord::new(finding).map(|r| serde_json::to_value(&r)) {
+            Some(Ok(record)) => record,
+            Some(Err(e)) => {
+                error!("Unable to serialise finding for the webhook: {}", e);
+                return;
+            }
+            None => return,
+        };
+        let entry = Entry {
+            record,
+            summary: summary(finding),
+        };
+
+        if let Some(ref sender) = self.sender {
+            match sender.try_send(entry) {
+                Ok(()) => {}
+                Err(TrySendError::Full(entry)) => {
+                    warn!("Webhook queue full, dropping {}", entry.summary)
+                }
+                Err(TrySendError::Disconnected(_)) => error!("Webhook worker stopped"),
+            }
+        }
+    }
+}
+
+impl Drop for Webhook {
+    fn drop(&mut self) {
+        self.sender = None;
+        if let Some(worker) = self.worker.take() {
+            match self.done.recv_timeout(self.shutdown_timeout) {
+                Err(RecvTimeoutError::Timeout) => {
+                    warn!("Gave up waiting for the webhook to post the queued findings")
+                }
+                _ => {
+                    let _ = worker.join();
+                }
+            }
+        }
+    }
+}
+
+struct Worker {
+    config: WebhookConfig,
+    agent: ureq::Agent,
+    last_request: Option<Instant>,
+}
+
+impl Worker {
+    fn new(config: WebhookConfig, agent: ureq::Agent) -> Self {
+        Worker {
+            config,
+            agent,
+            last_request: None,
+        }
+    }
+
+    fn run(&mut self, receiver: Receiver<Entry>) {
+        let batch_size = self.config.batch_size.max(1);
+        let interval = Duration::from_secs(self.config.batch_interval_secs);
+
+        // Blocks for the first finding of a batch, then waits up to the batch
+        // interval for the rest
+        while let Ok(first) = receiver.recv() {
+            let deadline = Instant::now() + interval;
+            let mut batch = vec![first];
+            let mut open = true;
+
+            while batch.len() < batch_size {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+                match receiver.recv_timeout(timeout) {
+                    Ok(entry) => batch.push(entry),
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        open = false;
+                        break;
+                    }
+                }
+            }
+
+            self.post(&batch);
+
+            if !open {
+                break;
+            }
+        }
+    }
+
+    fn post(&mut self, batch: &[Entry]) {
+        let payload = match self.config.template {
+            WebhookTemplate::Slack => json!({
+                "text": batch
+                    .iter()
+                    .map(|entry| entry.summary.as_str())
+                    .collect::<Vec<&str>>()
+                    .join("\n"),
+            }),
+            WebhookTemplate::Json => json!({
+                "findings": batch.iter().map(|entry| &entry.record).collect::<Vec<&Value>>(),
+            }),
+        };
+
+        let mut backoff = Backoff::new(self.config.retry.clone());
+        let mut attempt = 0;
+
+        loop {
+            self.rate_limit();
+
+            let mut request = self.agent.post(&self.config.url);
+            for (name, value) in &self.config.headers {
+                request = request.set(name, value);
+            }
+
+            let retry = match request.send_json(&payload) {
+                Ok(_) => return,
+                // Rate limited or a server side error, worth another try
+                Err(ureq::Error::Status(status, _)) if status == 429 || status >= 500 => {
+                    warn!("Webhook returned status {}", status);
+                    true
+                }
+                Err(ureq::Error::Status(status, _)) => {
+                    error!(
+                        "Webhook rejected {} findings with status {}",
+                        batch.len(),
+                        status
+                    );
+                    false
+                }
+                Err(e) => {
+                    warn!("Webhook request failed: {}", e);
+                    true
+                }
+            };
+
+            attempt += 1;
+            if !retry || attempt > self.config.max_retries {
+                error!(
+                    "Dropping {} findings not delivered to the webhook",
+                    batch.len()
+                );
+                return;
+            }
+
+            thread::sleep(backoff.next_delay());
+        }
+    }
+
+    // Spaces the requests evenly to stay within max_requests_per_minute
+    fn rate_limit(&mut self) {
+        if self.config.max_requests_per_minute > 0 {
+            let spacing = Duration::from_secs(60) / self.config.max_requests_per_minute;
+            if let Some(last_request) = self.last_request {
+                let elapsed = last_request.elapsed();
+                if elapsed < spacing {
+                    thread::sleep(spacing - elapsed);
+                }
+            }
+        }
+        self.last_request = Some(Instant::now());
+    }
+}
+
+// One line in Slack markup, e.g. "*high* `paypal-login.com` score 94 ~ paypal"
+fn summary(finding: &Finding) -> String {
+    let primary = match finding.primary() {
+        Some(primary) => primary,
+        None => return String::new(),
+    };
+
+    let mut line = format!(
+        "*{}* `{}` score {}",
+        finding.severity,
+        primary.display_domain(),
+        finding.score
+    );
+
+    if let Some(ref identity) = finding.identity {
+        line.push_str(&format!(" ~ {}", identity));
+    }
+    if primary.punycode_detected {
+        line.push_str(&format!(" (Punycode: `{}`)", primary.original_domain));
+    }
+    if finding.verdicts.len() > 1 {
+        line.push_str(&format!(" +{} more names", finding.verdicts.len() - 1));
+    }
+    if let Some(cert_link) = finding
+        .certificate
+        .as_ref()
+        .and_then(|certificate| certificate.cert_link.as_ref())
+    {
+        line.push_str(&format!(" <{}|CT log entry>", cert_link));
+    }
+
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verdict::{Severity, Verdict};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    struct Request {
+        received: Instant,
+        body: Value,
+    }
+
+    // Answers each request with the next status, on a new connection every time
+    fn stand_in(statuses: Vec<u16>) -> (String, JoinHandle<Vec<Request>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+
+        let server = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for status in statuses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim().to_lowercase();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(value) = line.strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                requests.push(Request {
+                    received: Instant::now(),
+                    body: serde_json::from_slice(&body).unwrap(),
+                });
+
+                let mut stream = reader.into_inner();
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                )
+                .unwrap();
+            }
+            requests
+        });
+
+        (url, server)
+    }
+
+    fn config(url: String) -> WebhookConfig {
+        WebhookConfig {
+            url,
+            batch_size: 3,
+            batch_interval_secs: 1,
+            max_requests_per_minute: 300,
+            retry: BackoffConfig {
+                initial_delay_ms: 10,
+                max_delay_ms: 10,
+                multiplier: 1.0,
+                jitter: 0.0,
+            },
+            max_retries: 2,
+            ..WebhookConfig::default()
+        }
+    }
+
+    fn finding(domain: &str) -> Finding {
+        Finding::from(Verdict::scored(domain, 95, Severity::High))
+    }
+
+    fn domains(request: &Request) -> Vec<&str> {
+        request.body["findings"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|finding| finding["domain"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn batches_retries_and_spaces_requests() {
+        let (url, server) = stand_in(vec![503, 200, 200]);
+        let mut webhook = Webhook::new(config(url)).unwrap();
+
+        for index in 0..5 {
+            webhook.send(&finding(&format!("paypal-{}.com", index)));
+        }
+        drop(webhook);
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests.len(), 3);
+        // The first batch is posted again after the server error
+        assert_eq!(
+            domains(&requests[0]),
+            ["paypal-0.com", "paypal-1.com", "paypal-2.com"]
+        );
+        assert_eq!(domains(&requests[1]), domains(&requests[0]));
+        assert_eq!(domains(&requests[2]), ["paypal-3.com", "paypal-4.com"]);
+
+        // 300 requests per minute, 200ms apart
+        for pair in requests.windows(2) {
+            let spacing = pair[1].received.duration_since(pair[0].received);
+            assert!(spacing >= Duration::from_millis(190), "{:?}", spacing);
+        }
+    }
+
+    #[test]
+    fn gives_up_on_exit_after_the_shutdown_timeout() {
+        // Accepts the connection but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut config = config(format!("http://{}/hook", listener.local_addr().unwrap()));
+        config.timeout_secs = 30;
+        config.shutdown_timeout_secs = 1;
+
+        let mut webhook = Webhook::new(config).unwrap();
+        webhook.send(&finding("paypal-login.com"));
+        let _connection = listener.accept().unwrap();
+
+        let started = Instant::now();
+        drop(webhook);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
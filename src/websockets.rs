@@ -1,18 +1,34 @@
+use crate::archive::Recorder;
+use crate::backoff::Backoff;
+use crate::data::CertStreamConfig;
 use crate::errors::*;
+use crate::net;
+use crate::shutdown;
 use url::Url;
-use tungstenite::connect;
+use tungstenite::client;
 use tungstenite::Message;
 use tungstenite::protocol::WebSocket;
-use tungstenite::client::AutoStream;
-use tungstenite::handshake::client::Response;
+use tungstenite::client::{AutoStream, IntoClientRequest};
+use tungstenite::handshake::client::{Request, Response};
+use tungstenite::http::header::{HeaderName, HeaderValue};
+use tungstenite::stream::Stream as StreamSwitcher;
+use native_tls::{Certificate as TlsCertificate, TlsConnector};
+use std::fs;
+use std::io;
+use std::net::TcpStream;
 use std::sync::mpsc::{self, channel};
+use std::time::{Duration, Instant};
 
-static WEBSOCKET_URL: &str = "wss://certstream.calidog.io";
+// How often a blocked read wakes up to check the connection health and for shutdown
+static HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+// Longest wait to connect, and for each read of the TLS and websocket handshakes
+static CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub trait EventHandler {
     fn on_connect(&mut self);
     fn on_data_event(&mut self, event: String);
     fn on_error(&mut self, message: Error);
+    fn on_disconnect(&mut self) {}
 }
 
 #[allow(dead_code)]
@@ -29,28 +45,46 @@ pub struct Sender {
 }
 
 pub struct WebSockets {
+    config: CertStreamConfig,
     socket: Option<(WebSocket<AutoStream>, Response)>,
     _sender: Sender,
     event_handler: Option<Box<dyn EventHandler>>,
+    recorder: Option<Recorder>,
+}
+
+impl Default for WebSockets {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl WebSockets {
     pub fn new() -> WebSockets {
+        WebSockets::with_config(CertStreamConfig::default())
+    }
+
+    pub fn with_config(config: CertStreamConfig) -> WebSockets {
         let (tx, _rx) = channel::<WsMessage>();
         let sender = Sender { tx };
 
         WebSockets {
+            config,
             socket: None,
             _sender: sender,
             event_handler: None,
+            recorder: None,
         }
     }
 
     pub fn connect(&mut self) -> Result<()> {
-        let url = Url::parse(&WEBSOCKET_URL.to_string())?;
+        let url = Url::parse(&self.config.url)?;
+        let request = self.handshake_request(&url)?;
+        let stream = self.open_stream(&url)?;
 
-        match connect(url) {
+        match client(request, stream) {
             Ok(answer) => {
+                // Short reads from now on so the health checks run while idle
+                tcp_stream(answer.0.get_ref()).set_read_timeout(Some(HEALTH_CHECK_INTERVAL))?;
                 self.socket = Some(answer);
                 if let Some(ref mut h) = self.event_handler {
                     h.on_connect();
@@ -61,6 +95,57 @@ impl WebSockets {
         }
     }
 
+    fn handshake_request(&self, url: &Url) -> Result<Request> {
+        let mut request = url.as_str().into_client_request()?;
+
+        for (name, value) in &self.config.headers {
+            let header_name = HeaderName::from_bytes(name.as_bytes())
+                .chain_err(|| format!("Invalid header name {}", name))?;
+            let header_value = HeaderValue::from_str(value)
+                .chain_err(|| format!("Invalid value for header {}", name))?;
+            request.headers_mut().insert(header_name, header_value);
+        }
+
+        Ok(request)
+    }
+
+    fn open_stream(&self, url: &Url) -> Result<AutoStream> {
+        let host = url.host_str().ok_or("No host name in the URL")?;
+        let port = url.port_or_known_default().ok_or("No port in the URL")?;
+
+        let stream = net::connect_timeout((host, port), CONNECT_TIMEOUT)
+            .chain_err(|| format!("Unable to connect to {}", host))?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+
+        match url.scheme() {
+            "ws" => Ok(StreamSwitcher::Plain(stream)),
+            "wss" => match self.tls_connector()?.connect(host, stream) {
+                Ok(tls_stream) => Ok(StreamSwitcher::Tls(tls_stream)),
+                Err(e) => bail!(format!("Error during TLS handshake {}", e)),
+            },
+            scheme => bail!(format!("URL scheme {} not supported", scheme)),
+        }
+    }
+
+    fn tls_connector(&self) -> Result<TlsConnector> {
+        let tls = &self.config.tls;
+        let mut builder = TlsConnector::builder();
+
+        builder
+            .danger_accept_invalid_certs(tls.accept_invalid_certs)
+            .danger_accept_invalid_hostnames(tls.accept_invalid_hostnames);
+
+        if let Some(ref path) = tls.ca_certificate {
+            let pem = fs::read(path).chain_err(|| format!("Unable to read {}", path))?;
+            let certificate = TlsCertificate::from_pem(&pem)
+                .chain_err(|| format!("Invalid CA certificate {}", path))?;
+            builder.add_root_certificate(certificate);
+        }
+
+        builder.build().chain_err(|| "Unable to set up TLS")
+    }
+
     pub fn add_event_handler<H>(&mut self, handler: H)
     where
         H: EventHandler + 'static,
@@ -68,13 +153,72 @@ impl WebSockets {
         self.event_handler = Some(Box::new(handler));
     }
 
+    // Archive every raw message received before it is handled
+    pub fn set_recorder(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+    }
+
+    // Processes messages until the connection drops, then reconnects with an
+    // exponential backoff. The event handler, and any state it keeps, is reused
+    // across reconnections. Returns once shutdown is requested.
+    pub fn run(&mut self) {
+        let mut backoff = Backoff::new(self.config.reconnect.clone());
+
+        while !shutdown::requested() {
+            match self.connect() {
+                Ok(()) => {
+                    backoff.reset();
+                    let result = self.event_loop();
+                    self.socket = None;
+                    if shutdown::requested() {
+                        break;
+                    }
+
+                    if let Some(ref mut h) = self.event_handler {
+                        if let Err(e) = result {
+                            h.on_error(e);
+                        }
+                        h.on_disconnect();
+                    }
+                }
+                Err(e) => {
+                    if let Some(ref mut h) = self.event_handler {
+                        h.on_error(e);
+                    }
+                }
+            }
+
+            if shutdown::requested() {
+                break;
+            }
+            let delay = backoff.next_delay();
+            info!("Reconnecting to certstream in {:.1}s", delay.as_secs_f64());
+            shutdown::sleep(delay);
+        }
+    }
+
     pub fn event_loop(&mut self) -> Result<()> {
-        loop {
-            if let Some(ref mut socket) = self.socket {
-                let message = socket.0.read_message()?;
+        let idle_timeout = Duration::from_secs(self.config.idle_timeout_secs);
+        let ping_interval = Duration::from_secs(self.config.ping_interval_secs);
+        let mut last_received = Instant::now();
+        let mut last_ping = Instant::now();
 
-                match message {
+        while !shutdown::requested() {
+            let socket = match self.socket {
+                Some(ref mut socket) => &mut socket.0,
+                None => bail!("Not connected"),
+            };
+
+            match socket.read_message() {
+                Ok(message) => match message {
                     Message::Text(text) => {
+                        last_received = Instant::now();
+
+                        if let Some(ref mut recorder) = self.recorder {
+                            if let Err(e) = recorder.record(&text) {
+                                error!("Unable to record message: {}", e);
+                            }
+                        }
                         if let Some(ref mut h) = self.event_handler {
                             h.on_data_event(text);
                         }
@@ -82,8 +226,145 @@ impl WebSockets {
                     Message::Binary(_) => {}
                     Message::Ping(_) | Message::Pong(_) => {}
                     Message::Close(_) => {}
+                },
+                // Read timeout, used to check the connection health
+                Err(tungstenite::Error::Io(ref e))
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut
+                        || e.kind() == io::ErrorKind::Interrupted =>
+                {
+                    // Idle, write out what the recorder buffered
+                    if let Some(ref mut recorder) = self.recorder {
+                        if let Err(e) = recorder.flush() {
+                            error!("Unable to record message: {}", e);
+                        }
+                    }
                 }
+                Err(e) => return Err(e.into()),
+            }
+
+            if !idle_timeout.is_zero() && last_received.elapsed() >= idle_timeout {
+                bail!(format!(
+                    "No messages received for {}s",
+                    idle_timeout.as_secs()
+                ));
+            }
+
+            if !ping_interval.is_zero() && last_ping.elapsed() >= ping_interval {
+                socket.write_message(Message::Ping(Vec::new()))?;
+                last_ping = Instant::now();
             }
         }
+
+        Ok(())
+    }
+}
+
+fn tcp_stream(stream: &AutoStream) -> &TcpStream {
+    match stream {
+        StreamSwitcher::Plain(stream) => stream,
+        StreamSwitcher::Tls(stream) => stream.get_ref(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use tungstenite::handshake::server::{self, ErrorResponse};
+
+    struct Collector {
+        events: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl EventHandler for Collector {
+        fn on_connect(&mut self) {
+            self.events.lock().unwrap().push("connect".to_string());
+        }
+
+        fn on_data_event(&mut self, event: String) {
+            self.events.lock().unwrap().push(event);
+        }
+
+        fn on_error(&mut self, message: Error) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("error {}", message));
+        }
+    }
+
+    #[test]
+    // The handshake callback has to return the http error response
+    #[allow(clippy::result_large_err)]
+    fn receives_messages_from_a_certstream_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        // Accepts one client, sends it a message and closes the connection.
+        // Returns the authorization header of the handshake.
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut authorization = None;
+            let callback = |request: &server::Request, response: server::Response| {
+                authorization = request
+                    .headers()
+                    .get("Authorization")
+                    .map(|value| value.to_str().unwrap().to_string());
+                Ok::<_, ErrorResponse>(response)
+            };
+            let mut socket = tungstenite::accept_hdr(stream, callback).unwrap();
+
+            socket
+                .write_message(Message::Text(
+                    "{\"message_type\": \"heartbeat\"}".to_string(),
+                ))
+                .unwrap();
+            socket.close(None).unwrap();
+            while socket.read_message().is_ok() {}
+
+            authorization
+        });
+
+        let mut headers = BTreeMap::new();
+        headers.insert("Authorization".to_string(), "Bearer secret".to_string());
+        let config = CertStreamConfig {
+            url: format!("ws://127.0.0.1:{}", port),
+            headers,
+            idle_timeout_secs: 5,
+            ping_interval_secs: 0,
+            ..CertStreamConfig::default()
+        };
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut websockets = WebSockets::with_config(config);
+        websockets.add_event_handler(Collector {
+            events: events.clone(),
+        });
+
+        websockets.connect().unwrap();
+        assert!(websockets.event_loop().is_err());
+
+        assert_eq!(server.join().unwrap().as_deref(), Some("Bearer secret"));
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec!["connect", "{\"message_type\": \"heartbeat\"}"]
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_schemes() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = CertStreamConfig {
+            url: format!("http://{}", listener.local_addr().unwrap()),
+            ..CertStreamConfig::default()
+        };
+
+        let error = WebSockets::with_config(config).connect().unwrap_err();
+
+        assert_eq!(error.to_string(), "URL scheme http not supported");
     }
 }